@@ -1,33 +1,131 @@
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::io::Read;
 use std::path::{Path, PathBuf, MAIN_SEPARATOR};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
+use chrono::NaiveDate;
 use glob::glob;
+use log::{info, warn};
 use polars::prelude::*;
+use rayon::prelude::*;
 
-pub fn find_dir_by_pattern(base_dir: &PathBuf, dir_pattern: &str) -> Option<PathBuf> {
-    let pattern = format!(
-        "{}{}{}",
-        base_dir.to_string_lossy(),
-        MAIN_SEPARATOR,
-        dir_pattern
-    );
-    let dirs: Vec<_> = glob(&pattern)
-        .expect("Failed to read glob pattern")
-        .filter_map(std::result::Result::ok)
-        .filter(|path| path.is_dir())
-        .collect();
+/// Names of the columns in the dataframes and CSV files this crate produces.
+pub mod columns {
+    /// Path of the file, as a string.
+    pub const PATH: &str = "Path";
+    /// File name without the directory.
+    pub const FILENAME: &str = "Filename";
+    /// Date from the file name, as `yyyy-mm-dd`.
+    pub const DATE: &str = "Date";
+    /// Event number from the file name, as written there.
+    pub const EVENT: &str = "Event";
+    /// Image type from the file name, such as `RGB` or `NIR`.
+    pub const TYPE: &str = "Type";
+    /// Image ID from the file name, as an `i32`.
+    pub const ID: &str = "ID";
+
+    /// In frames from `match_frames`, the NIR file's columns carry a `_right` suffix. Only the
+    /// outer-joined frames have `EVENT_RIGHT`, since matched files share one event.
+    pub const PATH_RIGHT: &str = "Path_right";
+    pub const FILENAME_RIGHT: &str = "Filename_right";
+    pub const DATE_RIGHT: &str = "Date_right";
+    pub const EVENT_RIGHT: &str = "Event_right";
+    pub const TYPE_RIGHT: &str = "Type_right";
+    pub const ID_RIGHT: &str = "ID_right";
+
+    /// Columns of a match manifest, alongside `DATE` and `EVENT`.
+    pub const PATH_RGB: &str = "Path_rgb";
+    pub const PATH_NIR: &str = "Path_nir";
+
+    /// Columns of a moves manifest. `MODIFIED` is the destination's modification time in
+    /// nanoseconds since the Unix epoch, and `OPERATION` how the file was transferred.
+    pub const SOURCE: &str = "Source";
+    pub const DESTINATION: &str = "Destination";
+    pub const MODIFIED: &str = "Modified";
+    pub const OPERATION: &str = "Operation";
+}
+
+/// Failures callers may want to tell apart. Functions still return `anyhow::Result`; use
+/// `error.downcast_ref::<IxMatchError>()` to match on these.
+#[derive(Debug, thiserror::Error)]
+pub enum IxMatchError {
+    #[error("No directory matching '{pattern}' found in {}", .base.display())]
+    DirectoryNotFound { pattern: String, base: PathBuf },
+    #[error(
+        "Multiple directories matching '{pattern}' found in {}: {}",
+        .base.display(),
+        display_paths(.matches)
+    )]
+    AmbiguousDirectory {
+        pattern: String,
+        base: PathBuf,
+        matches: Vec<PathBuf>,
+    },
+    #[error("RGB and NIR directories are the same directory: {}", .0.display())]
+    SameDirectory(PathBuf),
+    #[error("Cannot parse '{filename}': {reason}")]
+    Parse { filename: String, reason: String },
+    #[error("Destination {} already exists", .0.display())]
+    Collision(PathBuf),
+    #[error("Failed to move {}", .path.display())]
+    Move {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+}
 
+fn display_paths(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|p| p.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Expand `{a,b}` alternatives in a glob pattern into one pattern per alternative.
+/// Braces may not be nested.
+fn expand_braces(pattern: &str) -> Vec<String> {
+    let Some(open) = pattern.find('{') else {
+        return vec![pattern.to_string()];
+    };
+    let Some(close) = pattern[open..].find('}').map(|i| open + i) else {
+        return vec![pattern.to_string()];
+    };
+
+    let (prefix, suffix) = (&pattern[..open], &pattern[close + 1..]);
+    pattern[open + 1..close]
+        .split(',')
+        .flat_map(|alt| expand_braces(&format!("{}{}{}", prefix, alt, suffix)))
+        .collect()
+}
+
+/// Find the single directory in `base_dir` matching `dir_pattern`. The pattern is a glob that may
+/// also contain `{a,b}` alternatives; special characters in `base_dir` itself are matched
+/// literally.
+pub fn find_dir_by_pattern(base_dir: impl AsRef<Path>, dir_pattern: &str) -> Option<PathBuf> {
+    let base_dir = base_dir.as_ref();
+    let dirs = match find_dirs_by_pattern(base_dir, dir_pattern) {
+        Ok(dirs) => dirs,
+        Err(e) => {
+            warn!("{:#}", e);
+            return None;
+        }
+    };
     match dirs.len() {
         1 => Some(dirs[0].clone()),
         0 => {
-            println!(
+            warn!(
                 "No directory matching '{}' found in {:?}",
                 dir_pattern, base_dir
             );
             None
         }
         _ => {
-            println!(
+            warn!(
                 "Multiple directories matching '{}' found in {:?}",
                 dir_pattern, base_dir
             );
@@ -36,50 +134,698 @@ pub fn find_dir_by_pattern(base_dir: &PathBuf, dir_pattern: &str) -> Option<Path
     }
 }
 
-pub fn make_iiq_df(iiq_files: &[PathBuf]) -> PolarsResult<DataFrame> {
-    // Filenames match pattern yyyy-mm-ddnnn_RGB_id.IIQ
-    let filenames = &iiq_files
+/// Like `find_dir_by_pattern`, but tries each of `dir_patterns` in order of priority and returns
+/// the directory of the first one that matches exactly one.
+pub fn find_dir_by_patterns(base_dir: impl AsRef<Path>, dir_patterns: &[&str]) -> Option<PathBuf> {
+    let base_dir = base_dir.as_ref();
+    let found = dir_patterns.iter().find_map(|pattern| {
+        let dirs = find_dirs_by_pattern(base_dir, pattern)
+            .map_err(|e| warn!("{:#}", e))
+            .ok()?;
+        match dirs.as_slice() {
+            [dir] => Some(dir.clone()),
+            _ => None,
+        }
+    });
+    if found.is_none() {
+        warn!(
+            "No single directory matching any of {:?} found in {:?}",
+            dir_patterns, base_dir
+        );
+    }
+    found
+}
+
+/// Every directory in `base_dir` matching `dir_pattern`, sorted. See `find_dir_by_pattern`.
+/// Fails if `dir_pattern` is not a valid glob.
+pub fn find_dirs_by_pattern(base_dir: &Path, dir_pattern: &str) -> Result<Vec<PathBuf>> {
+    let base = glob::Pattern::escape(&base_dir.to_string_lossy());
+    let mut dirs = Vec::new();
+    for p in expand_braces(dir_pattern) {
+        let paths = glob(&format!("{}{}{}", base, MAIN_SEPARATOR, p))
+            .with_context(|| format!("Invalid directory pattern '{}'", dir_pattern))?;
+        dirs.extend(paths.filter_map(std::result::Result::ok));
+    }
+    dirs.retain(|path| path.is_dir());
+    dirs.sort();
+    dirs.dedup();
+    Ok(dirs)
+}
+
+/// The fields encoded in an IIQ filename.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IiqFilename {
+    pub date: String,
+    pub event: String,
+    pub kind: String,
+    pub id: i32,
+}
+
+/// Parse a filename following the pattern yyyy-mm-ddnnn_RGB_id.IIQ
+pub fn parse_iiq_filename(filename: &str) -> Result<IiqFilename> {
+    let invalid = |reason: String| IxMatchError::Parse {
+        filename: filename.to_string(),
+        reason,
+    };
+    let mut parts = filename.split('_');
+    let head = parts.next().unwrap_or_default();
+
+    if head.chars().count() <= 10 {
+        bail!(invalid(
+            "too short to start with a yyyy-mm-dd date and event number".to_string()
+        ));
+    }
+    let date: String = head.chars().take(10).collect();
+    NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+        .map_err(|_| invalid("no yyyy-mm-dd date at the start".to_string()))?;
+    let event: String = head.chars().skip(10).collect();
+    let kind = parts
+        .next()
+        .ok_or_else(|| invalid("no image type".to_string()))?
+        .to_string();
+    let id = parts
+        .next()
+        .and_then(|p| p.split('.').next())
+        .ok_or_else(|| invalid("no image ID".to_string()))?;
+    let id = id
+        .parse::<i32>()
+        .map_err(|_| invalid(format!("invalid image ID '{}'", id)))?;
+
+    Ok(IiqFilename {
+        date,
+        event,
+        kind,
+        id,
+    })
+}
+
+/// Parse the name of the file at `path`, ignoring its first `prefix_len` characters.
+fn iiq_filename(path: &Path, prefix_len: usize) -> Result<IiqFilename> {
+    if path.to_str().is_none() {
+        bail!("{} is not valid UTF-8", path.display());
+    }
+    let filename = path
+        .file_name()
+        .with_context(|| format!("{} has no file name", path.display()))?;
+    let filename: String = filename
+        .to_string_lossy()
+        .chars()
+        .skip(prefix_len)
+        .collect();
+    parse_iiq_filename(&filename)
+}
+
+/// Build a dataframe with one row per file and the columns `Path`, `Filename`, `Date`, `Event`,
+/// `Type` (all strings) and `ID` (`i32`). Fails if any file name can't be parsed.
+pub fn make_iiq_df(iiq_files: &[PathBuf]) -> Result<DataFrame> {
+    make_prefixed_iiq_df(iiq_files, 0)
+}
+
+fn make_prefixed_iiq_df(iiq_files: &[PathBuf], prefix_len: usize) -> Result<DataFrame> {
+    let names = iiq_files
         .iter()
-        .map(|p| p.file_name().unwrap().to_string_lossy().into_owned())
-        .collect::<Vec<String>>();
+        .map(|p| iiq_filename(p, prefix_len))
+        .collect::<Result<Vec<_>>>()?;
+    build_iiq_df(iiq_files, &names)
+}
+
+/// Like `make_iiq_df`, but files whose names can't be parsed are left out of the dataframe and
+/// returned alongside it instead of failing the whole batch.
+pub fn make_iiq_df_lenient(iiq_files: &[PathBuf]) -> Result<(DataFrame, Vec<PathBuf>)> {
+    make_prefixed_iiq_df_lenient(iiq_files, 0)
+}
+
+fn make_prefixed_iiq_df_lenient(
+    iiq_files: &[PathBuf],
+    prefix_len: usize,
+) -> Result<(DataFrame, Vec<PathBuf>)> {
+    let mut paths = Vec::new();
+    let mut names = Vec::new();
+    let mut malformed = Vec::new();
+    for path in iiq_files {
+        match iiq_filename(path, prefix_len) {
+            Ok(name) => {
+                paths.push(path.clone());
+                names.push(name);
+            }
+            Err(e) => {
+                warn!("{:#}", e);
+                malformed.push(path.clone());
+            }
+        }
+    }
+    Ok((build_iiq_df(&paths, &names)?, malformed))
+}
 
-    df!(
-        "Path" => &iiq_files
+fn build_iiq_df(iiq_files: &[PathBuf], names: &[IiqFilename]) -> Result<DataFrame> {
+    let df = df!(
+        columns::PATH => &iiq_files
             .iter()
             .map(|p| p.to_string_lossy().into_owned())
             .collect::<Vec<String>>(),
-        "Filename" => filenames,
-        "Date" => &filenames
-            .iter()
-            .map(|p| p[..10].to_string())
-            .collect::<Vec<String>>(),
-        "Event" => &filenames
+        columns::FILENAME => &iiq_files
             .iter()
-            .map(|p| p.split('_').next().unwrap()[10..].to_string())
+            .map(|p| p.file_name().unwrap().to_string_lossy().into_owned())
             .collect::<Vec<String>>(),
-        "Type" => &filenames
-            .iter()
-            .map(|p| p.split('_').nth(1).unwrap().to_string())
-            .collect::<Vec<String>>(),
-        "ID" => &filenames
+        columns::DATE => &names.iter().map(|n| n.date.clone()).collect::<Vec<String>>(),
+        columns::EVENT => &names.iter().map(|n| n.event.clone()).collect::<Vec<String>>(),
+        columns::TYPE => &names.iter().map(|n| n.kind.clone()).collect::<Vec<String>>(),
+        columns::ID => &names.iter().map(|n| n.id).collect::<Vec<i32>>(),
+    )?;
+    Ok(df)
+}
+
+/// Find files in `dir` whose names end with any of `extensions`, ignoring case.
+pub fn find_files(dir: &Path, extensions: &[&str]) -> Result<Vec<PathBuf>> {
+    find_files_recursive(dir, extensions, 0, &[])
+}
+
+/// Like `find_files`, but also searches sub-directories up to `max_depth` levels below `dir`.
+/// Sub-directories whose names match a glob pattern in `exclude` are not entered. A pattern that
+/// isn't valid glob syntax only matches the exact name.
+pub fn find_files_recursive(
+    dir: &Path,
+    extensions: &[&str],
+    max_depth: usize,
+    exclude: &[&str],
+) -> Result<Vec<PathBuf>> {
+    find_files_iter(dir, extensions, max_depth, exclude).collect()
+}
+
+/// Like `find_files_recursive`, but reads up to `jobs` directories at once, which helps with
+/// large trees on storage with high latency. The same files are returned, in a different order.
+pub fn find_files_recursive_parallel(
+    dir: &Path,
+    extensions: &[&str],
+    max_depth: usize,
+    exclude: &[&str],
+    jobs: usize,
+) -> Result<Vec<PathBuf>> {
+    find_files_iter(dir, extensions, max_depth, exclude).collect_parallel(jobs)
+}
+
+/// Lazily yield the files `find_files_recursive` would return, reading each directory only when
+/// the previous one has been exhausted.
+pub fn find_files_iter(
+    dir: &Path,
+    extensions: &[&str],
+    max_depth: usize,
+    exclude: &[&str],
+) -> FindFiles {
+    FindFiles {
+        extensions: extensions.iter().map(|e| e.to_lowercase()).collect(),
+        exclude: exclude
             .iter()
-            .map(|p| {
-                let id = p.split('_').nth(2).unwrap().to_string();
-                id.trim_end_matches(".IIQ").parse::<i32>().unwrap()
+            .map(|e| {
+                glob::Pattern::new(e)
+                    .or_else(|_| glob::Pattern::new(&glob::Pattern::escape(e)))
+                    .unwrap()
             })
-            .collect::<Vec<i32>>(),
-    )
+            .collect(),
+        max_depth,
+        dirs: vec![(dir.to_path_buf(), 0)],
+        current: None,
+        skip_unreadable: false,
+        skip_hidden: false,
+        skip_dirs: Vec::new(),
+        skipped: Vec::new(),
+    }
+}
+
+/// A directory entry worth keeping while searching for files.
+enum Found {
+    File(PathBuf),
+    Dir(PathBuf),
+}
+
+/// Iterator over matching files, returned by `find_files_iter`.
+pub struct FindFiles {
+    extensions: Vec<String>,
+    exclude: Vec<glob::Pattern>,
+    max_depth: usize,
+    dirs: Vec<(PathBuf, usize)>,
+    current: Option<(std::fs::ReadDir, usize)>,
+    skip_unreadable: bool,
+    skip_hidden: bool,
+    skip_dirs: Vec<PathBuf>,
+    skipped: Vec<PathBuf>,
+}
+
+impl FindFiles {
+    /// Pass over sub-directories that can't be read, such as system folders on removable
+    /// drives, instead of yielding an error for them. The starting directory must still be
+    /// readable.
+    pub fn skip_unreadable(mut self, skip: bool) -> Self {
+        self.skip_unreadable = skip;
+        self
+    }
+
+    /// Leave out files and sub-directories whose names start with a dot, such as the `._`
+    /// resource files macOS writes to non-Apple filesystems.
+    pub fn skip_hidden(mut self, skip: bool) -> Self {
+        self.skip_hidden = skip;
+        self
+    }
+
+    /// Leave out these directories wherever they appear in the tree. Directories that don't
+    /// exist are ignored.
+    pub fn skip_dirs(mut self, dirs: &[PathBuf]) -> Self {
+        self.skip_dirs = dirs.iter().filter_map(|d| d.canonicalize().ok()).collect();
+        self
+    }
+
+    /// Sub-directories passed over so far because they couldn't be read.
+    pub fn skipped(&self) -> &[PathBuf] {
+        &self.skipped
+    }
+
+    /// Collect the remaining files, reading up to `jobs` directories at once. Each level of the
+    /// tree is read before the next, so files come out in a different order than when iterating.
+    pub fn collect_parallel(&mut self, jobs: usize) -> Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        if let Some((entries, depth)) = self.current.take() {
+            for entry in entries.flatten() {
+                match self.visit(&entry, depth)? {
+                    Some(Found::File(path)) => files.push(path),
+                    Some(Found::Dir(path)) => self.dirs.push((path, depth + 1)),
+                    None => {}
+                }
+            }
+        }
+
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(jobs).build()?;
+        while !self.dirs.is_empty() {
+            let level = std::mem::take(&mut self.dirs);
+            // None for directories that couldn't be read and are skipped
+            let read: Vec<Result<Option<Vec<Found>>>> = pool.install(|| {
+                level
+                    .par_iter()
+                    .map(|(dir, depth)| {
+                        let entries = match dir.read_dir() {
+                            Ok(entries) => entries,
+                            Err(_) if self.skip_unreadable && *depth > 0 => return Ok(None),
+                            Err(e) => {
+                                let e = anyhow::Error::new(e);
+                                return Err(e.context(format!("Failed to read {}", dir.display())));
+                            }
+                        };
+                        let mut found = Vec::new();
+                        for entry in entries.flatten() {
+                            found.extend(self.visit(&entry, *depth)?);
+                        }
+                        Ok(Some(found))
+                    })
+                    .collect()
+            });
+            for ((dir, depth), found) in level.into_iter().zip(read) {
+                let Some(found) = found? else {
+                    self.skipped.push(dir);
+                    continue;
+                };
+                for found in found {
+                    match found {
+                        Found::File(path) => files.push(path),
+                        Found::Dir(path) => self.dirs.push((path, depth + 1)),
+                    }
+                }
+            }
+        }
+        Ok(files)
+    }
+
+    /// Whether `entry`, in a directory `depth` levels below the starting one, is a matching file
+    /// or a sub-directory to search.
+    fn visit(&self, entry: &std::fs::DirEntry, depth: usize) -> Result<Option<Found>> {
+        let file_type = entry.file_type()?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if self.skip_hidden && name.starts_with('.') {
+            return Ok(None);
+        }
+        if file_type.is_dir() {
+            let skipped = || {
+                self.exclude.iter().any(|p| p.matches(&name))
+                    || (!self.skip_dirs.is_empty()
+                        && entry
+                            .path()
+                            .canonicalize()
+                            .is_ok_and(|path| self.skip_dirs.contains(&path)))
+            };
+            if depth < self.max_depth && !skipped() {
+                return Ok(Some(Found::Dir(entry.path())));
+            }
+        } else if file_type.is_file() {
+            let name = name.to_lowercase();
+            if self.extensions.iter().any(|e| name.ends_with(e.as_str())) {
+                return Ok(Some(Found::File(entry.path())));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Iterator for FindFiles {
+    type Item = Result<PathBuf>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let Some((entries, depth)) = &mut self.current else {
+                let (dir, depth) = self.dirs.pop()?;
+                match dir.read_dir() {
+                    Ok(entries) => self.current = Some((entries, depth)),
+                    Err(_) if self.skip_unreadable && depth > 0 => self.skipped.push(dir),
+                    Err(e) => {
+                        let e = anyhow::Error::new(e);
+                        return Some(Err(e.context(format!("Failed to read {}", dir.display()))));
+                    }
+                }
+                continue;
+            };
+            let depth = *depth;
+            let Some(entry) = entries.next() else {
+                self.current = None;
+                continue;
+            };
+            let Ok(entry) = entry else {
+                continue;
+            };
+            match self.visit(&entry, depth) {
+                Ok(Some(Found::File(path))) => return Some(Ok(path)),
+                Ok(Some(Found::Dir(path))) => self.dirs.push((path, depth + 1)),
+                Ok(None) => {}
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// Leading bytes of a Phase One IIQ file, and of the little- and big-endian TIFF files IIQs are
+/// based on.
+const IIQ_SIGNATURES: [&[u8; 4]; 3] = [b"IIII", b"II*\0", b"MM\0*"];
+
+/// Check whether the file at `path` starts with an IIQ or TIFF signature.
+pub fn has_iiq_signature(path: &Path) -> Result<bool> {
+    let mut header = [0u8; 4];
+    let mut file = std::fs::File::open(path)?;
+    match file.read_exact(&mut header) {
+        Ok(()) => Ok(IIQ_SIGNATURES.iter().any(|sig| **sig == header)),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Split `files` into those with and without a valid IIQ signature.
+pub fn validate_signatures(files: &[PathBuf]) -> Result<(Vec<PathBuf>, Vec<PathBuf>)> {
+    let mut valid = Vec::new();
+    let mut invalid = Vec::new();
+    for path in files {
+        if has_iiq_signature(path)? {
+            valid.push(path.clone());
+        } else {
+            invalid.push(path.clone());
+        }
+    }
+    Ok((valid, invalid))
+}
+
+/// Split `files` into those of at least `min_size` bytes and those smaller, keeping their order.
+/// File sizes are read in parallel, since stat latency dominates on network shares.
+pub fn split_by_size(files: &[PathBuf], min_size: u64) -> Result<(Vec<PathBuf>, Vec<PathBuf>)> {
+    if min_size == 0 {
+        return Ok((files.to_vec(), Vec::new()));
+    }
+    let sizes = files
+        .par_iter()
+        .map(|path| Ok(std::fs::metadata(path)?.len()))
+        .collect::<Result<Vec<u64>>>()?;
+
+    let mut large_enough = Vec::new();
+    let mut undersized = Vec::new();
+    for (path, size) in files.iter().zip(sizes) {
+        if size < min_size {
+            undersized.push(path.clone());
+        } else {
+            large_enough.push(path.clone());
+        }
+    }
+    Ok((large_enough, undersized))
 }
 
-pub fn find_files(dir: &Path, extension: &str) -> Result<Vec<PathBuf>> {
-    let files = dir
-        .read_dir()?
-        .filter_map(|entry| entry.ok())
-        .filter(|entry| entry.file_type().unwrap().is_file())
-        .filter(|entry| entry.file_name().to_string_lossy().ends_with(extension))
-        .map(|entry| entry.path())
+/// Split `files` into those that can be opened and have their metadata read, and those that
+/// can't, warning about each of the latter.
+pub fn split_accessible(files: &[PathBuf]) -> (Vec<PathBuf>, Vec<PathBuf>) {
+    let errors: Vec<Option<std::io::Error>> = files
+        .par_iter()
+        .map(|path| std::fs::File::open(path).and_then(|f| f.metadata()).err())
         .collect();
-    Ok(files)
+
+    let mut accessible = Vec::new();
+    let mut inaccessible = Vec::new();
+    for (path, error) in files.iter().zip(errors) {
+        match error {
+            Some(e) => {
+                warn!("Cannot read {}: {}, skipping", path.display(), e);
+                inaccessible.push(path.clone());
+            }
+            None => accessible.push(path.clone()),
+        }
+    }
+    (accessible, inaccessible)
+}
+
+/// Split `files` into unique files and exact copies of another file in the list. Of each set of
+/// identical files, the one whose name sorts first is kept, which for IIQs is the earliest.
+pub fn split_duplicate_files(files: &[PathBuf]) -> Result<(Vec<PathBuf>, Vec<PathBuf>)> {
+    let mut sorted: Vec<&PathBuf> = files.iter().collect();
+    sorted.sort_by(|a, b| a.file_name().cmp(&b.file_name()).then(a.cmp(b)));
+
+    let mut by_size: std::collections::HashMap<u64, Vec<&PathBuf>> = Default::default();
+    let mut unique = Vec::new();
+    let mut duplicates = Vec::new();
+    for path in sorted {
+        let kept = by_size.entry(std::fs::metadata(path)?.len()).or_default();
+        let mut is_copy = false;
+        for original in kept.iter() {
+            if files_identical(original, path)? {
+                warn!("{} is a copy of {}", path.display(), original.display());
+                is_copy = true;
+                break;
+            }
+        }
+        if is_copy {
+            duplicates.push(path.clone());
+        } else {
+            kept.push(path);
+            unique.push(path.clone());
+        }
+    }
+    Ok((unique, duplicates))
+}
+
+/// How files are transferred into their destination directory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FileOp {
+    /// Rename the file, removing it from the source directory.
+    #[default]
+    Move,
+    /// Copy the file, leaving the source intact.
+    Copy,
+    /// Create a symbolic link to the file, leaving the source intact.
+    Symlink,
+}
+
+impl FileOp {
+    /// Name of the operation as written to a moves manifest.
+    pub fn name(self) -> &'static str {
+        match self {
+            FileOp::Move => "move",
+            FileOp::Copy => "copy",
+            FileOp::Symlink => "symlink",
+        }
+    }
+
+    /// The operation with the given `name`, if any.
+    pub fn from_name(name: &str) -> Option<Self> {
+        [FileOp::Move, FileOp::Copy, FileOp::Symlink]
+            .into_iter()
+            .find(|op| op.name() == name)
+    }
+
+    fn verb(self) -> &'static str {
+        match self {
+            FileOp::Move => "Moving",
+            FileOp::Copy => "Copying",
+            FileOp::Symlink => "Linking",
+        }
+    }
+
+    /// Apply the operation. With `verify`, copied data is compared against the source before
+    /// anything is removed.
+    fn apply(self, src: &Path, dest: &Path, verify: bool) -> std::io::Result<()> {
+        match self {
+            FileOp::Move => move_file(src, dest, verify),
+            FileOp::Copy => {
+                std::fs::copy(src, dest)?;
+                if verify {
+                    verify_copy(src, dest)?;
+                }
+                Ok(())
+            }
+            FileOp::Symlink => symlink_file(&std::path::absolute(src)?, dest),
+        }
+    }
+}
+
+/// Rename `src` to `dest`, falling back to copying and removing the original when they are on
+/// different filesystems. The copy keeps the original's access and modification times.
+fn move_file(src: &Path, dest: &Path, verify: bool) -> std::io::Result<()> {
+    match std::fs::rename(src, dest) {
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+            copy_with_times(src, dest)?;
+            if verify {
+                verify_copy(src, dest)?;
+            }
+            std::fs::remove_file(src)
+        }
+        result => result,
+    }
+}
+
+/// Fail if `dest` isn't byte-identical to `src`, removing the bad copy.
+fn verify_copy(src: &Path, dest: &Path) -> std::io::Result<()> {
+    if files_identical(src, dest)? {
+        return Ok(());
+    }
+    std::fs::remove_file(dest)?;
+    Err(std::io::Error::other(format!(
+        "{} differs from {} after copying",
+        dest.display(),
+        src.display()
+    )))
+}
+
+/// Check whether the files at `a` and `b` have the same contents.
+pub fn files_identical(a: &Path, b: &Path) -> std::io::Result<bool> {
+    if std::fs::metadata(a)?.len() != std::fs::metadata(b)?.len() {
+        return Ok(false);
+    }
+    let mut a = std::io::BufReader::new(std::fs::File::open(a)?);
+    let mut b = std::io::BufReader::new(std::fs::File::open(b)?);
+    let mut buf_a = [0u8; 8192];
+    let mut buf_b = [0u8; 8192];
+    loop {
+        let n = a.read(&mut buf_a)?;
+        if n == 0 {
+            return Ok(true);
+        }
+        b.read_exact(&mut buf_b[..n])?;
+        if buf_a[..n] != buf_b[..n] {
+            return Ok(false);
+        }
+    }
+}
+
+fn copy_with_times(src: &Path, dest: &Path) -> std::io::Result<()> {
+    let metadata = std::fs::metadata(src)?;
+    std::fs::copy(src, dest)?;
+    let times = std::fs::FileTimes::new()
+        .set_accessed(metadata.accessed()?)
+        .set_modified(metadata.modified()?);
+    std::fs::File::options()
+        .write(true)
+        .open(dest)?
+        .set_times(times)
+}
+
+#[cfg(unix)]
+fn symlink_file(src: &Path, dest: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(src, dest)
+}
+
+#[cfg(windows)]
+fn symlink_file(src: &Path, dest: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_file(src, dest)
+}
+
+/// What to do when a file with the same name already exists at the destination.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum CollisionPolicy {
+    /// Stop with an error before anything is overwritten.
+    #[default]
+    Error,
+    /// Leave the source file where it is.
+    Skip,
+    /// Append a numeric suffix to the destination file name.
+    Rename,
+}
+
+/// How to change the case of file names at their destination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum NameCase {
+    /// Lowercase the whole file name.
+    Lower,
+    /// Uppercase the whole file name.
+    Upper,
+    /// Lowercase only the extension, e.g. `.IIQ` to `.iiq`.
+    LowerExtension,
+    /// Uppercase only the extension.
+    UpperExtension,
+}
+
+impl NameCase {
+    /// Apply the case to `name`. Names that aren't valid UTF-8 are left as they are.
+    pub fn apply(self, name: &std::ffi::OsStr) -> OsString {
+        let Some(name) = name.to_str() else {
+            return name.to_os_string();
+        };
+        let (stem, extension) = match name.rsplit_once('.') {
+            Some((stem, extension)) if !stem.is_empty() => (stem, Some(extension)),
+            _ => (name, None),
+        };
+        match (self, extension) {
+            (NameCase::Lower, _) => name.to_lowercase().into(),
+            (NameCase::Upper, _) => name.to_uppercase().into(),
+            (NameCase::LowerExtension, Some(extension)) => {
+                format!("{}.{}", stem, extension.to_lowercase()).into()
+            }
+            (NameCase::UpperExtension, Some(extension)) => {
+                format!("{}.{}", stem, extension.to_uppercase()).into()
+            }
+            (_, None) => name.into(),
+        }
+    }
+}
+
+/// Resolve the destination for `file_name` in `dir`, honouring `policy`.
+/// Returns `None` when the file should be skipped.
+fn resolve_destination(
+    dir: &Path,
+    file_name: &std::ffi::OsStr,
+    policy: CollisionPolicy,
+    taken: &HashSet<PathBuf>,
+) -> Result<Option<PathBuf>> {
+    let dest = dir.join(file_name);
+    let exists = |p: &Path| p.exists() || taken.contains(p);
+    if !exists(&dest) {
+        return Ok(Some(dest));
+    }
+
+    match policy {
+        CollisionPolicy::Error => bail!(IxMatchError::Collision(dest)),
+        CollisionPolicy::Skip => Ok(None),
+        CollisionPolicy::Rename => {
+            let name = Path::new(file_name);
+            let stem = name.file_stem().unwrap_or_default().to_string_lossy();
+            let ext = name
+                .extension()
+                .map(|e| format!(".{}", e.to_string_lossy()))
+                .unwrap_or_default();
+            let dest = (1..)
+                .map(|i| dir.join(format!("{}_{}{}", stem, i, ext)))
+                .find(|p| !exists(p))
+                .unwrap();
+            Ok(Some(dest))
+        }
+    }
 }
 
 pub fn move_unmatched_files(
@@ -88,27 +834,2900 @@ pub fn move_unmatched_files(
     column_name: &str,
     subdir_name: &str,
     dry_run: bool,
-) -> Result<()> {
-    let path_series = df.column(column_name)?.str().unwrap();
-    let paths: Vec<PathBuf> = path_series
-        .into_iter()
-        .filter_map(|s| s.map(PathBuf::from))
+    file_op: FileOp,
+    collision: CollisionPolicy,
+) -> Result<Vec<FileMove>> {
+    let paths = get_df_column_as_paths(df, column_name)?;
+    let unmatched_dir = dir.join(subdir_name);
+    move_files(&paths, &unmatched_dir, dry_run, file_op, collision, None)
+}
+
+/// A single file transfer performed (or planned, in a dry run) by `move_files`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileMove {
+    pub source: PathBuf,
+    pub destination: PathBuf,
+}
+
+/// Reverse `moves`, most recent first. Copies are removed from their destination and moved files
+/// are renamed back to their source. Every move is attempted even if an earlier one fails.
+pub fn undo_moves(moves: &[FileMove], file_op: FileOp) -> Result<()> {
+    let moves: Vec<(FileMove, FileOp)> = moves.iter().map(|m| (m.clone(), file_op)).collect();
+    undo_each(&moves)
+}
+
+/// Reverse a single transfer made with `file_op`. Nothing is overwritten, and a copy or link is
+/// only removed while its source still exists, so undoing with the wrong operation can't lose
+/// the last copy of a file.
+fn undo_move(m: &FileMove, file_op: FileOp) -> std::io::Result<()> {
+    let source_exists = std::fs::symlink_metadata(&m.source).is_ok();
+    match file_op {
+        FileOp::Move if source_exists => Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("{} already exists", m.source.display()),
+        )),
+        FileOp::Move => move_file(&m.destination, &m.source, false),
+        FileOp::Copy | FileOp::Symlink if !source_exists => Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("{} no longer exists", m.source.display()),
+        )),
+        FileOp::Symlink if !std::fs::symlink_metadata(&m.destination)?.is_symlink() => {
+            Err(std::io::Error::other(format!(
+                "{} is not a symbolic link",
+                m.destination.display()
+            )))
+        }
+        FileOp::Copy | FileOp::Symlink => std::fs::remove_file(&m.destination),
+    }
+}
+
+/// Reverse each move with its own operation, most recent first.
+fn undo_each(moves: &[(FileMove, FileOp)]) -> Result<()> {
+    let mut failed = 0;
+    for (m, file_op) in moves.iter().rev() {
+        if let Err(e) = undo_move(m, *file_op) {
+            warn!("Could not undo {}: {}", m.destination.display(), e);
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        bail!("Failed to undo {} of {} moves", failed, moves.len());
+    }
+    Ok(())
+}
+
+/// Modification time of the file or link at `path` in nanoseconds since the Unix epoch.
+fn modified_nanos(path: &Path) -> Option<i64> {
+    let modified = std::fs::symlink_metadata(path).ok()?.modified().ok()?;
+    let since_epoch = modified.duration_since(std::time::UNIX_EPOCH).ok()?;
+    i64::try_from(since_epoch.as_nanos()).ok()
+}
+
+/// Write `moves` to a CSV file with the current modification time of each destination and the
+/// operation that made it, so the run can later be reversed with `undo_run`.
+pub fn write_moves_manifest(moves: &[FileMove], file_op: FileOp, path: &Path) -> Result<()> {
+    let moves: Vec<(FileMove, FileOp)> = moves.iter().map(|m| (m.clone(), file_op)).collect();
+    write_each_move(&moves, path)
+}
+
+/// Write a moves manifest recording the operation of each move separately.
+fn write_each_move(moves: &[(FileMove, FileOp)], path: &Path) -> Result<()> {
+    let source: Vec<String> = moves
+        .iter()
+        .map(|(m, _)| m.source.to_string_lossy().into_owned())
+        .collect();
+    let destination: Vec<String> = moves
+        .iter()
+        .map(|(m, _)| m.destination.to_string_lossy().into_owned())
         .collect();
+    let modified: Vec<Option<i64>> = moves
+        .iter()
+        .map(|(m, _)| modified_nanos(&m.destination))
+        .collect();
+    let operation: Vec<&str> = moves.iter().map(|(_, op)| op.name()).collect();
+    let mut manifest = df!(
+        columns::SOURCE => source,
+        columns::DESTINATION => destination,
+        columns::MODIFIED => modified,
+        columns::OPERATION => operation,
+    )?;
 
-    // Create 'unmatched' directory
-    let unmatched_dir = dir.join(subdir_name);
-    if !dry_run {
-        std::fs::create_dir_all(&unmatched_dir)?;
+    let mut file = std::fs::File::create(path)?;
+    CsvWriter::new(&mut file).finish(&mut manifest)?;
+    Ok(())
+}
+
+/// The commands of `write_move_script` for a POSIX shell.
+fn posix_script(moves: &[FileMove], file_op: FileOp) -> Result<String> {
+    let mut script = String::from("#!/bin/sh\nset -e\n");
+    let mut dirs = HashSet::new();
+    for m in moves {
+        if let Some(dir) = m.destination.parent() {
+            if dirs.insert(dir) {
+                script.push_str(&format!("mkdir -p {}\n", shell_quote(dir)));
+            }
+        }
+        let destination = shell_quote(&m.destination);
+        script.push_str(&format!(
+            "if [ -e {0} ] || [ -L {0} ]; then echo {0} already exists >&2; exit 1; fi\n",
+            destination
+        ));
+        let command = match file_op {
+            FileOp::Move => format!("mv -- {}", shell_quote(&m.source)),
+            FileOp::Copy => format!("cp -p -- {}", shell_quote(&m.source)),
+            FileOp::Symlink => {
+                format!("ln -s -- {}", shell_quote(&std::path::absolute(&m.source)?))
+            }
+        };
+        script.push_str(&format!("{} {}\n", command, destination));
     }
+    Ok(script)
+}
 
-    // Move files to 'unmatched' directory
-    for path in paths {
-        let dest = unmatched_dir.join(path.file_name().unwrap());
-        println!("{} -> {}", path.display(), dest.display());
-        if !dry_run {
-            std::fs::rename(&path, &dest)?;
+/// The commands of `write_move_script` for a Windows batch file.
+fn cmd_script(moves: &[FileMove], file_op: FileOp) -> Result<String> {
+    let mut script = String::from("@echo off\r\n");
+    let mut dirs = HashSet::new();
+    for m in moves {
+        if let Some(dir) = m.destination.parent() {
+            if dirs.insert(dir) {
+                let dir = cmd_quote(dir);
+                script.push_str(&format!("if not exist {0} mkdir {0} || exit /b 1\r\n", dir));
+            }
         }
+        let (source, destination) = (cmd_quote(&m.source), cmd_quote(&m.destination));
+        script.push_str(&format!(
+            "if exist {0} (echo {0} already exists 1>&2 & exit /b 1)\r\n",
+            destination
+        ));
+        let command = match file_op {
+            FileOp::Move => format!("move {} {}", source, destination),
+            FileOp::Copy => format!("copy /b {} {}", source, destination),
+            FileOp::Symlink => format!(
+                "mklink {} {}",
+                destination,
+                cmd_quote(&std::path::absolute(&m.source)?)
+            ),
+        };
+        script.push_str(&format!("{} >nul || exit /b 1\r\n", command));
     }
+    Ok(script)
+}
+
+/// Quote `path` for a POSIX shell.
+fn shell_quote(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', "'\\''"))
+}
 
+/// Quote `path` for a Windows batch file.
+fn cmd_quote(path: &Path) -> String {
+    format!("\"{}\"", path.to_string_lossy().replace('%', "%%"))
+}
+
+/// Write `moves` as a script of file commands, depending on `file_op`, so the transfers can be
+/// reviewed and run by hand. On Windows this is a batch file of `move`, `copy` or `mklink`
+/// commands, and elsewhere a POSIX shell script of `mv`, `cp -p` or `ln -s` commands. The script
+/// creates destination directories, never overwrites an existing file, and stops at the first
+/// failing command.
+pub fn write_move_script(moves: &[FileMove], file_op: FileOp, path: &Path) -> Result<()> {
+    let script = if cfg!(windows) {
+        cmd_script(moves, file_op)?
+    } else {
+        posix_script(moves, file_op)?
+    };
+    std::fs::write(path, script)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))?;
+    }
     Ok(())
 }
+
+/// Reverse the moves recorded by `write_moves_manifest`, most recent first, each with the
+/// operation recorded for it. Destinations that no longer exist are skipped, and any changed
+/// since the manifest was written are reported before being restored.
+pub fn undo_run(manifest: &Path) -> Result<()> {
+    let df = read_csv(manifest)?;
+    let sources = df.column(columns::SOURCE)?.str()?;
+    let destinations = df.column(columns::DESTINATION)?.str()?;
+    let modified = df.column(columns::MODIFIED)?.cast(&DataType::Int64)?;
+    let modified = modified.i64()?;
+    let operations = df
+        .column(columns::OPERATION)
+        .with_context(|| format!("{} doesn't record the file operation", manifest.display()))?
+        .str()?;
+
+    let mut moves = Vec::new();
+    for (((source, destination), recorded), operation) in sources
+        .into_iter()
+        .zip(destinations)
+        .zip(modified)
+        .zip(operations)
+    {
+        let (Some(source), Some(destination)) = (source, destination) else {
+            continue;
+        };
+        let operation = operation.unwrap_or_default();
+        let Some(file_op) = FileOp::from_name(operation) else {
+            bail!("Unknown file operation '{}' for {}", operation, destination);
+        };
+        let destination = PathBuf::from(destination);
+        if std::fs::symlink_metadata(&destination).is_err() {
+            warn!("{} no longer exists, skipping", destination.display());
+            continue;
+        }
+        if recorded.is_some() && modified_nanos(&destination) != recorded {
+            warn!("{} was modified after the run", destination.display());
+        }
+        let m = FileMove {
+            source: PathBuf::from(source),
+            destination,
+        };
+        moves.push((m, file_op));
+    }
+
+    info!("Undoing {} moves from {}", moves.len(), manifest.display());
+    undo_each(&moves)
+}
+
+/// Carry out the moves planned in a manifest from `write_moves_manifest`, typically one written
+/// by a dry run on another machine. Each row is transferred with the operation recorded in it;
+/// if `file_op` is given, every row must record that operation. Transfers otherwise follow
+/// `options` (collision policy, verification, retries, jobs and cancellation), and are undone if
+/// one fails. With `options.moves_manifest`, the transfers made are recorded for `undo_run`.
+pub fn apply_manifest(
+    manifest: &Path,
+    file_op: Option<FileOp>,
+    options: &ProcessOptions,
+) -> Result<Vec<FileMove>> {
+    let df = read_csv(manifest)?;
+    let sources = df.column(columns::SOURCE)?.str()?;
+    let destinations = df.column(columns::DESTINATION)?.str()?;
+    let operations = df
+        .column(columns::OPERATION)
+        .with_context(|| format!("{} doesn't record the file operation", manifest.display()))?
+        .str()?;
+
+    // Consecutive transfers into the same directory with the same operation form one batch,
+    // keeping the planned order
+    type Batch = (PathBuf, FileOp, Vec<(PathBuf, OsString)>);
+    let mut batches: Vec<Batch> = Vec::new();
+    for ((source, destination), operation) in sources.into_iter().zip(destinations).zip(operations)
+    {
+        let (Some(source), Some(destination)) = (source, destination) else {
+            continue;
+        };
+        let operation = operation.unwrap_or_default();
+        let Some(op) = FileOp::from_name(operation) else {
+            bail!("Unknown file operation '{}' for {}", operation, destination);
+        };
+        if let Some(requested) = file_op.filter(|&requested| requested != op) {
+            bail!(
+                "{} was planned as a {} but a {} was requested",
+                destination,
+                op.name(),
+                requested.name()
+            );
+        }
+        let destination = Path::new(destination);
+        let (Some(dir), Some(name)) = (destination.parent(), destination.file_name()) else {
+            bail!("{} is not a file path", destination.display());
+        };
+        let file = (PathBuf::from(source), name.to_os_string());
+        match batches.last_mut() {
+            Some((last_dir, last_op, files)) if last_dir == dir && *last_op == op => {
+                files.push(file)
+            }
+            _ => batches.push((dir.to_path_buf(), op, vec![file])),
+        }
+    }
+
+    info!("Applying moves from {}", manifest.display());
+    let transfer = Transfer {
+        dry_run: options.dry_run,
+        // Replaced by the operation recorded for each batch
+        file_op: FileOp::Move,
+        collision: options.collision,
+        verify: options.verify,
+        retries: options.retries,
+        jobs: options.jobs,
+        cancel: options.cancel.as_ref(),
+        // The planned names are used as they are
+        name_case: None,
+    };
+    let mut moves: Vec<(FileMove, FileOp)> = Vec::new();
+    for (dest_dir, file_op, files) in batches {
+        let transfer = Transfer {
+            file_op,
+            ..transfer
+        };
+        match move_named_files(&files, &dest_dir, transfer, None) {
+            Ok(moved) => moves.extend(moved.into_iter().map(|m| (m, file_op))),
+            Err(e) => return Err(rollback_each(&moves, options.dry_run, e)),
+        }
+        if transfer.is_cancelled() {
+            warn!("Cancelled after {} transfers", moves.len());
+            break;
+        }
+    }
+
+    if let Some(path) = &options.moves_manifest {
+        write_each_move(&moves, path)?;
+        info!("Wrote moves manifest to {}", path.display());
+    }
+    Ok(moves.into_iter().map(|(m, _)| m).collect())
+}
+
+/// Move (or copy) `paths` into `dest_dir`, creating it if needed, and return the transfers made.
+/// If a transfer fails, the ones already made are undone before the error is returned.
+/// `progress` is called with `(files done, total files)` after each file.
+pub fn move_files(
+    paths: &[PathBuf],
+    dest_dir: &Path,
+    dry_run: bool,
+    file_op: FileOp,
+    collision: CollisionPolicy,
+    progress: Option<&mut dyn FnMut(usize, usize)>,
+) -> Result<Vec<FileMove>> {
+    let transfer = Transfer {
+        dry_run,
+        file_op,
+        collision,
+        verify: false,
+        retries: 0,
+        jobs: 1,
+        cancel: None,
+        name_case: None,
+    };
+    move_named_files(&keep_names(paths), dest_dir, transfer, progress)
+}
+
+/// Shared flag for stopping a run from another thread, such as a GUI's cancel button. Clones
+/// share the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask the run to stop before its next file transfer.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl PartialEq for CancelToken {
+    /// Tokens are equal when they share a flag.
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// How `move_named_files` transfers each file.
+#[derive(Debug, Clone, Copy)]
+struct Transfer<'a> {
+    dry_run: bool,
+    file_op: FileOp,
+    collision: CollisionPolicy,
+    verify: bool,
+    /// How many times to retry a transfer that failed with a transient error.
+    retries: u32,
+    /// How many files to transfer at once.
+    jobs: usize,
+    /// Stops transfers that haven't started yet once cancelled.
+    cancel: Option<&'a CancelToken>,
+    /// Changes the case of each file name at the destination.
+    name_case: Option<NameCase>,
+}
+
+impl Transfer<'_> {
+    /// The name a file called `name` is given at its destination.
+    fn dest_name(&self, name: &std::ffi::OsStr) -> OsString {
+        match self.name_case {
+            Some(case) => case.apply(name),
+            None => name.to_os_string(),
+        }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancel.is_some_and(CancelToken::is_cancelled)
+    }
+
+    fn apply(&self, src: &Path, dest: &Path) -> std::io::Result<()> {
+        let mut attempt = 0;
+        loop {
+            match self.file_op.apply(src, dest, self.verify) {
+                Err(e) if attempt < self.retries && is_transient(&e) => {
+                    attempt += 1;
+                    let delay = std::time::Duration::from_millis(100 << attempt.min(6));
+                    warn!("{}: {}, retrying in {:?}", src.display(), e, delay);
+                    std::thread::sleep(delay);
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Whether an I/O error may go away if the operation is simply tried again, as happens on
+/// unreliable network shares.
+fn is_transient(error: &std::io::Error) -> bool {
+    use std::io::ErrorKind::*;
+    matches!(
+        error.kind(),
+        Interrupted
+            | WouldBlock
+            | TimedOut
+            | ResourceBusy
+            | ConnectionReset
+            | ConnectionAborted
+            | NetworkDown
+            | StaleNetworkFileHandle
+    )
+}
+
+/// Pair each path with its current file name.
+fn keep_names(paths: &[PathBuf]) -> Vec<(PathBuf, OsString)> {
+    paths
+        .iter()
+        .map(|p| (p.clone(), p.file_name().unwrap().to_os_string()))
+        .collect()
+}
+
+/// `move_files`, but each source is given the file name it's paired with at the destination.
+/// Once the transfer is cancelled, the remaining files are left alone and the transfers already
+/// made are returned.
+fn move_named_files(
+    files: &[(PathBuf, OsString)],
+    dest_dir: &Path,
+    transfer: Transfer,
+    mut progress: Option<&mut dyn FnMut(usize, usize)>,
+) -> Result<Vec<FileMove>> {
+    let Transfer {
+        dry_run, file_op, ..
+    } = transfer;
+    if !dry_run {
+        std::fs::create_dir_all(dest_dir)
+            .with_context(|| format!("Failed to create {}", dest_dir.display()))?;
+        if transfer.jobs > 1 {
+            return move_named_files_parallel(files, dest_dir, transfer, progress);
+        }
+    }
+
+    let mut moves = Vec::new();
+    let mut taken = HashSet::new();
+    for (i, (path, name)) in files.iter().enumerate() {
+        if transfer.is_cancelled() {
+            break;
+        }
+        let name = transfer.dest_name(name);
+        match resolve_destination(dest_dir, &name, transfer.collision, &taken) {
+            Ok(Some(dest)) => {
+                taken.insert(dest.clone());
+                info!("{} -> {}", path.display(), dest.display());
+                if !dry_run {
+                    if let Err(e) = transfer.apply(path, &dest) {
+                        let e = anyhow::Error::new(IxMatchError::Move {
+                            path: path.clone(),
+                            source: e,
+                        });
+                        return Err(rollback(&moves, file_op, dry_run, e));
+                    }
+                }
+                moves.push(FileMove {
+                    source: path.clone(),
+                    destination: dest,
+                });
+            }
+            Ok(None) => warn!(
+                "{} already exists, leaving {} in place",
+                dest_dir.join(&name).display(),
+                path.display()
+            ),
+            Err(e) => return Err(rollback(&moves, file_op, dry_run, e)),
+        }
+
+        if let Some(progress) = progress.as_mut() {
+            progress(i + 1, files.len());
+        }
+    }
+
+    Ok(moves)
+}
+
+/// `move_named_files` with up to `transfer.jobs` files transferred at once. Destinations are
+/// still resolved one by one so collisions are handled the same way. Every transfer is attempted;
+/// if any fail, the successful ones are undone. `progress` is only called once all are done.
+fn move_named_files_parallel(
+    files: &[(PathBuf, OsString)],
+    dest_dir: &Path,
+    transfer: Transfer,
+    progress: Option<&mut dyn FnMut(usize, usize)>,
+) -> Result<Vec<FileMove>> {
+    let mut planned = Vec::new();
+    let mut taken = HashSet::new();
+    for (path, name) in files {
+        let name = transfer.dest_name(name);
+        match resolve_destination(dest_dir, &name, transfer.collision, &taken)? {
+            Some(dest) => {
+                taken.insert(dest.clone());
+                info!("{} -> {}", path.display(), dest.display());
+                planned.push(FileMove {
+                    source: path.clone(),
+                    destination: dest,
+                });
+            }
+            None => warn!(
+                "{} already exists, leaving {} in place",
+                dest_dir.join(&name).display(),
+                path.display()
+            ),
+        }
+    }
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(transfer.jobs)
+        .build()?;
+    // Transfers that hadn't started when the run was cancelled come back as None
+    let results: Vec<Option<std::io::Result<()>>> = pool.install(|| {
+        planned
+            .par_iter()
+            .map(|m| (!transfer.is_cancelled()).then(|| transfer.apply(&m.source, &m.destination)))
+            .collect()
+    });
+
+    let total = planned.len();
+    let mut moves = Vec::new();
+    let mut failed = 0;
+    let mut first_failure = None;
+    for (m, result) in planned.into_iter().zip(results) {
+        match result {
+            Some(Ok(())) => moves.push(m),
+            Some(Err(e)) => {
+                warn!("Failed to move {}: {}", m.source.display(), e);
+                failed += 1;
+                first_failure.get_or_insert(IxMatchError::Move {
+                    path: m.source,
+                    source: e,
+                });
+            }
+            None => {}
+        }
+    }
+    if let Some(progress) = progress {
+        progress(files.len(), files.len());
+    }
+
+    // Report the first failure as the serial path would, so callers can downcast either way
+    if let Some(first_failure) = first_failure {
+        let e = anyhow::Error::new(first_failure)
+            .context(format!("Failed to move {} of {} files", failed, total));
+        return Err(rollback(&moves, transfer.file_op, false, e));
+    }
+    Ok(moves)
+}
+
+/// Group `files` by their directory relative to `band_dir`, recreating that directory under
+/// `dest_dir`.
+fn mirror_batches(
+    band_dir: &Path,
+    dest_dir: &Path,
+    files: &[PathBuf],
+) -> Vec<(PathBuf, Vec<(PathBuf, OsString)>)> {
+    let mut groups: std::collections::BTreeMap<PathBuf, Vec<PathBuf>> = Default::default();
+    for path in files {
+        let relative = path
+            .parent()
+            .and_then(|parent| parent.strip_prefix(band_dir).ok())
+            .unwrap_or(Path::new(""));
+        groups
+            .entry(dest_dir.join(relative))
+            .or_default()
+            .push(path.clone());
+    }
+    groups
+        .into_iter()
+        .map(|(dir, paths)| (dir, keep_names(&paths)))
+        .collect()
+}
+
+/// Check whether a file can be created in `dir`, or in its closest existing ancestor if it
+/// hasn't been created yet. With `read_only`, nothing is written and only a directory with no
+/// write permission at all is reported; ownership, groups and ACLs are not taken into account.
+fn is_writable(dir: &Path, read_only: bool) -> bool {
+    static PROBES: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+    let Some(dir) = dir.ancestors().find(|d| d.is_dir()) else {
+        return false;
+    };
+    if read_only {
+        return std::fs::metadata(dir).is_ok_and(|m| !m.permissions().readonly());
+    }
+    // A probe left behind by a crashed run must not make the directory look unwritable
+    loop {
+        let probe = dir.join(format!(
+            ".ix-match-write-check-{}-{}",
+            std::process::id(),
+            PROBES.fetch_add(1, Ordering::Relaxed)
+        ));
+        match std::fs::File::options()
+            .write(true)
+            .create_new(true)
+            .open(&probe)
+        {
+            Ok(_) => return std::fs::remove_file(&probe).is_ok(),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(_) => return false,
+        }
+    }
+}
+
+/// Undo `moves` after `error` interrupted a batch and return the error to propagate.
+fn rollback(
+    moves: &[FileMove],
+    file_op: FileOp,
+    dry_run: bool,
+    error: anyhow::Error,
+) -> anyhow::Error {
+    let moves: Vec<(FileMove, FileOp)> = moves.iter().map(|m| (m.clone(), file_op)).collect();
+    rollback_each(&moves, dry_run, error)
+}
+
+/// `rollback` for moves made with different operations.
+fn rollback_each(
+    moves: &[(FileMove, FileOp)],
+    dry_run: bool,
+    error: anyhow::Error,
+) -> anyhow::Error {
+    if dry_run || moves.is_empty() {
+        return error;
+    }
+    warn!("Rolling back {} completed moves", moves.len());
+    match undo_each(moves) {
+        Ok(()) => error,
+        Err(undo_error) => error.context(undo_error.to_string()),
+    }
+}
+
+/// Write the RGB/NIR pairs of a matched dataframe to a CSV file, one row per pair.
+pub fn write_match_manifest(df: &DataFrame, path: &Path) -> Result<()> {
+    let mut manifest = df.select([
+        columns::DATE,
+        columns::EVENT,
+        columns::PATH,
+        columns::PATH_RIGHT,
+    ])?;
+    manifest.rename(columns::PATH, columns::PATH_RGB)?;
+    manifest.rename(columns::PATH_RIGHT, columns::PATH_NIR)?;
+
+    let mut file = std::fs::File::create(path)?;
+    CsvWriter::new(&mut file).finish(&mut manifest)?;
+    Ok(())
+}
+
+/// Read the pairs written by `write_match_manifest` back in, e.g. to feed them to another tool
+/// after the files have been sorted.
+pub fn read_match_manifest(path: &Path) -> Result<Vec<MatchedPair>> {
+    // Read every column as text, so event numbers keep their leading zeros
+    let mut manifest = CsvReadOptions::default()
+        .with_has_header(true)
+        .with_infer_schema_length(Some(0))
+        .try_into_reader_with_file_path(Some(path.to_path_buf()))?
+        .finish()
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    manifest.rename(columns::PATH_RGB, columns::PATH)?;
+    manifest.rename(columns::PATH_NIR, columns::PATH_RIGHT)?;
+    matched_pairs(&manifest)
+}
+
+fn read_csv(path: &Path) -> Result<DataFrame> {
+    CsvReadOptions::default()
+        .with_has_header(true)
+        .try_into_reader_with_file_path(Some(path.to_path_buf()))?
+        .finish()
+        .with_context(|| format!("Failed to read {}", path.display()))
+}
+
+/// Differences between a saved match manifest and a freshly matched dataframe.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ManifestDiff {
+    /// Pairs that are new and share no file with a saved pair.
+    pub added: Vec<(PathBuf, PathBuf)>,
+    /// Saved pairs that no longer match and share no file with a new pair.
+    pub removed: Vec<(PathBuf, PathBuf)>,
+    /// Saved pairs, each with the new pair that replaced it by reusing one of its files.
+    pub changed: Vec<((PathBuf, PathBuf), (PathBuf, PathBuf))>,
+}
+
+impl ManifestDiff {
+    /// Whether the manifest and the dataframe hold the same pairs.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Compare the pairs in a matched dataframe with those in a manifest written by
+/// `write_match_manifest`.
+pub fn diff_match_manifest(df: &DataFrame, manifest: &Path) -> Result<ManifestDiff> {
+    let saved = read_csv(manifest)?;
+    let saved: Vec<(PathBuf, PathBuf)> = get_df_column_as_paths(&saved, columns::PATH_RGB)?
+        .into_iter()
+        .zip(get_df_column_as_paths(&saved, columns::PATH_NIR)?)
+        .collect();
+    let current: Vec<(PathBuf, PathBuf)> = get_df_column_as_paths(df, columns::PATH)?
+        .into_iter()
+        .zip(get_df_column_as_paths(df, columns::PATH_RIGHT)?)
+        .collect();
+
+    let mut removed: Vec<_> = saved
+        .iter()
+        .filter(|pair| !current.contains(pair))
+        .cloned()
+        .collect();
+    let mut diff = ManifestDiff::default();
+    for pair in current.into_iter().filter(|pair| !saved.contains(pair)) {
+        match removed
+            .iter()
+            .position(|(rgb, nir)| *rgb == pair.0 || *nir == pair.1)
+        {
+            Some(i) => diff.changed.push((removed.remove(i), pair)),
+            None => diff.added.push(pair),
+        }
+    }
+    diff.removed = removed;
+    Ok(diff)
+}
+
+/// Keep only rows whose `Date` falls within `start..=end`. Either bound may be omitted.
+pub fn filter_by_date(
+    df: &DataFrame,
+    start: Option<NaiveDate>,
+    end: Option<NaiveDate>,
+) -> Result<DataFrame> {
+    // Dates are stored as zero-padded yyyy-mm-dd strings, so they compare lexically
+    let dates = df.column(columns::DATE)?.str()?;
+    let mut mask = BooleanChunked::full("mask", true, df.height());
+    if let Some(start) = start {
+        mask = mask & dates.gt_eq(start.format("%Y-%m-%d").to_string().as_str());
+    }
+    if let Some(end) = end {
+        mask = mask & dates.lt_eq(end.format("%Y-%m-%d").to_string().as_str());
+    }
+    Ok(df.filter(&mask)?)
+}
+
+/// Remove rows dated outside `options.earliest_plausible..=options.latest_plausible`, adding their
+/// paths to `malformed`. Fails instead unless `options.skip_malformed` is set.
+fn reject_implausible_dates(
+    df: DataFrame,
+    mut malformed: Vec<PathBuf>,
+    options: &ProcessOptions,
+) -> Result<(DataFrame, Vec<PathBuf>)> {
+    let (earliest, latest) = (options.earliest_plausible, options.latest_plausible);
+    let plausible = filter_by_date(&df, earliest, latest)?;
+    if plausible.height() == df.height() {
+        return Ok((df, malformed));
+    }
+
+    let kept: HashSet<PathBuf> = get_df_column_as_paths(&plausible, columns::PATH)?
+        .into_iter()
+        .collect();
+    for path in get_df_column_as_paths(&df, columns::PATH)? {
+        if kept.contains(&path) {
+            continue;
+        }
+        if !options.skip_malformed {
+            bail!("{} has an implausible date", path.display());
+        }
+        warn!("{} has an implausible date", path.display());
+        malformed.push(path);
+    }
+    Ok((plausible, malformed))
+}
+
+/// Event numbers shared by more than one row of `df`, in sorted order.
+pub fn find_duplicate_events(df: &DataFrame) -> Result<Vec<String>> {
+    let mut counts = std::collections::BTreeMap::new();
+    for event in df.column(columns::EVENT)?.str()?.into_iter().flatten() {
+        *counts.entry(event).or_insert(0) += 1;
+    }
+    Ok(counts
+        .into_iter()
+        .filter(|(_, count)| *count > 1)
+        .map(|(event, _)| event.to_string())
+        .collect())
+}
+
+/// The first `limit` rows of `df` in order of date, event and ID.
+pub fn first_files(df: &DataFrame, limit: usize) -> Result<DataFrame> {
+    let sorted = df.sort(
+        [columns::DATE, columns::EVENT, columns::ID],
+        Default::default(),
+    )?;
+    Ok(sorted.head(Some(limit)))
+}
+
+/// The dataframes produced by joining RGB and NIR files on their event number.
+#[derive(Debug, Clone)]
+pub struct MatchFrames {
+    /// Inner join: one row per matched RGB/NIR pair.
+    pub matched: DataFrame,
+    /// Outer join of both bands.
+    pub joined: DataFrame,
+    /// Rows of `joined` with no NIR counterpart.
+    pub unmatched_rgb: DataFrame,
+    /// Rows of `joined` with no RGB counterpart.
+    pub unmatched_nir: DataFrame,
+}
+
+/// Pair the rows of two dataframes built by `make_iiq_df` on their `Event`. NIR columns in the
+/// result carry a `_right` suffix. The frames can come from anywhere as long as they share that
+/// schema. A file listed in both frames, directly or through a link, is only treated as RGB so
+/// it is never paired with itself.
+pub fn match_frames(rgb_df: &DataFrame, nir_df: &DataFrame) -> Result<MatchFrames> {
+    let canonical = |path: &PathBuf| path.canonicalize().unwrap_or_else(|_| path.clone());
+    let rgb_files: HashSet<PathBuf> = get_df_column_as_paths(rgb_df, columns::PATH)?
+        .iter()
+        .map(canonical)
+        .collect();
+    let mask: BooleanChunked = get_df_column_as_paths(nir_df, columns::PATH)?
+        .iter()
+        .map(|path| !rgb_files.contains(&canonical(path)))
+        .collect();
+    let nir_df = &nir_df.filter(&mask)?;
+
+    let matched = rgb_df
+        .inner_join(nir_df, &[columns::EVENT], &[columns::EVENT])?
+        .sort(
+            [
+                columns::DATE,
+                columns::EVENT,
+                columns::ID,
+                columns::ID_RIGHT,
+            ],
+            Default::default(),
+        )?;
+    let joined = rgb_df.outer_join(nir_df, &[columns::EVENT], &[columns::EVENT])?;
+
+    let mask = joined.column(columns::TYPE_RIGHT)?.is_null();
+    let unmatched_rgb = joined.filter(&mask)?;
+    let mask = joined.column(columns::TYPE)?.is_null();
+    let unmatched_nir = joined.filter(&mask)?;
+
+    Ok(MatchFrames {
+        matched,
+        joined,
+        unmatched_rgb,
+        unmatched_nir,
+    })
+}
+
+/// Read the non-null values of a string column as paths.
+pub fn get_df_column_as_paths(df: &DataFrame, column_name: &str) -> Result<Vec<PathBuf>> {
+    let paths = df
+        .column(column_name)?
+        .str()?
+        .into_iter()
+        .filter_map(|s| s.map(PathBuf::from))
+        .collect();
+    Ok(paths)
+}
+
+/// An RGB and a NIR file sharing an event number.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchedPair {
+    pub rgb: PathBuf,
+    pub nir: PathBuf,
+    /// Date of the RGB file, as `yyyy-mm-dd`.
+    pub date: String,
+    pub event: String,
+}
+
+/// Read the rows of a matched dataframe from `match_frames` as pairs.
+pub fn matched_pairs(df: &DataFrame) -> Result<Vec<MatchedPair>> {
+    let rgb = df.column(columns::PATH)?.str()?;
+    let nir = df.column(columns::PATH_RIGHT)?.str()?;
+    let date = df.column(columns::DATE)?.str()?;
+    let event = df.column(columns::EVENT)?.str()?;
+    let pairs = rgb
+        .into_iter()
+        .zip(nir)
+        .zip(date)
+        .zip(event)
+        .filter_map(|(((rgb, nir), date), event)| {
+            Some(MatchedPair {
+                rgb: PathBuf::from(rgb?),
+                nir: PathBuf::from(nir?),
+                date: date?.to_string(),
+                event: event?.to_string(),
+            })
+        })
+        .collect();
+    Ok(pairs)
+}
+
+/// Pairs whose RGB file size divided by their NIR file size falls outside `min_ratio` to
+/// `max_ratio`. Files from one rig are usually of similar size, so an outlier suggests the
+/// event numbers paired the wrong captures. Pairs with an empty file always count as mismatched.
+pub fn find_size_mismatches(
+    pairs: &[MatchedPair],
+    min_ratio: Option<f64>,
+    max_ratio: Option<f64>,
+) -> Result<Vec<MatchedPair>> {
+    if min_ratio.is_none() && max_ratio.is_none() {
+        return Ok(Vec::new());
+    }
+    let sizes = pairs
+        .par_iter()
+        .map(|pair| {
+            let rgb = std::fs::metadata(&pair.rgb)?.len();
+            let nir = std::fs::metadata(&pair.nir)?.len();
+            Ok((rgb, nir))
+        })
+        .collect::<Result<Vec<(u64, u64)>>>()?;
+
+    Ok(pairs
+        .iter()
+        .zip(sizes)
+        .filter(|(_, (rgb, nir))| {
+            // An empty file is never a plausible partner, and its ratio is undefined
+            if *rgb == 0 || *nir == 0 {
+                return true;
+            }
+            let ratio = *rgb as f64 / *nir as f64;
+            min_ratio.is_some_and(|min| ratio < min) || max_ratio.is_some_and(|max| ratio > max)
+        })
+        .map(|(pair, _)| pair.clone())
+        .collect())
+}
+
+/// Matched pairs and leftovers from matching two lists of files.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MatchResult {
+    pub matched: Vec<MatchedPair>,
+    pub unmatched_rgb: Vec<PathBuf>,
+    pub unmatched_nir: Vec<PathBuf>,
+}
+
+impl MatchResult {
+    fn from_frames(frames: &MatchFrames) -> Result<Self> {
+        Ok(Self {
+            matched: matched_pairs(&frames.matched)?,
+            unmatched_rgb: get_df_column_as_paths(&frames.unmatched_rgb, columns::PATH)?,
+            unmatched_nir: get_df_column_as_paths(&frames.unmatched_nir, columns::PATH_RIGHT)?,
+        })
+    }
+}
+
+/// Match RGB and NIR files by event number. Nothing on disk is modified.
+pub fn match_images(rgb_files: &[PathBuf], nir_files: &[PathBuf]) -> Result<MatchResult> {
+    let frames = match_frames(&make_iiq_df(rgb_files)?, &make_iiq_df(nir_files)?)?;
+    MatchResult::from_frames(&frames)
+}
+
+/// List the RGB and NIR files in two band directories that have no partner, without moving
+/// anything.
+pub fn find_unmatched(rgb_dir: &Path, nir_dir: &Path) -> Result<(Vec<PathBuf>, Vec<PathBuf>)> {
+    let result = MatchResult::from_frames(&plan_images(rgb_dir, nir_dir)?)?;
+    Ok((result.unmatched_rgb, result.unmatched_nir))
+}
+
+/// The files of every band that share one event number.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BandGroup {
+    pub event: String,
+    /// Files for each band, in the order the bands were given. Usually one file per band.
+    pub files: Vec<Vec<PathBuf>>,
+}
+
+impl BandGroup {
+    /// Whether every band has at least one file for this event.
+    pub fn is_complete(&self) -> bool {
+        self.files.iter().all(|files| !files.is_empty())
+    }
+
+    /// Indices of the bands with no file for this event.
+    pub fn missing_bands(&self) -> Vec<usize> {
+        (0..self.files.len())
+            .filter(|&i| self.files[i].is_empty())
+            .collect()
+    }
+}
+
+/// Group the files of any number of bands by event number, ordered by event. Groups missing a
+/// band are included, so incomplete sets can be reported or moved aside.
+pub fn match_bands(bands: &[Vec<PathBuf>]) -> Result<Vec<BandGroup>> {
+    let mut groups: std::collections::BTreeMap<String, Vec<Vec<PathBuf>>> = Default::default();
+    for (band, files) in bands.iter().enumerate() {
+        let df = make_iiq_df(files)?;
+        let events = df.column(columns::EVENT)?.str()?;
+        let paths = df.column(columns::PATH)?.str()?;
+        for (event, path) in events.into_iter().zip(paths) {
+            let (Some(event), Some(path)) = (event, path) else {
+                continue;
+            };
+            groups
+                .entry(event.to_string())
+                .or_insert_with(|| vec![Vec::new(); bands.len()])[band]
+                .push(PathBuf::from(path));
+        }
+    }
+    Ok(groups
+        .into_iter()
+        .map(|(event, files)| BandGroup { event, files })
+        .collect())
+}
+
+/// Make sure every file appears in at most one matched pair. When several files in a band share
+/// an event number, pairs are taken in order of RGB then NIR ID and the first claim on a file
+/// wins. Files that lose every claim are added to the unmatched frames. Returns the updated frames
+/// and the dropped pairs.
+pub fn enforce_one_to_one(frames: MatchFrames) -> Result<(MatchFrames, DataFrame)> {
+    let matched = frames.matched.sort(
+        [columns::ID, columns::ID_RIGHT],
+        SortMultipleOptions::default(),
+    )?;
+    let rgb = get_df_column_as_paths(&matched, columns::PATH)?;
+    let nir = get_df_column_as_paths(&matched, columns::PATH_RIGHT)?;
+
+    let mut used_rgb = HashSet::new();
+    let mut used_nir = HashSet::new();
+    let keep: BooleanChunked = rgb
+        .iter()
+        .zip(&nir)
+        .map(|(r, n)| {
+            if used_rgb.contains(r) || used_nir.contains(n) {
+                false
+            } else {
+                used_rgb.insert(r);
+                used_nir.insert(n);
+                true
+            }
+        })
+        .collect();
+    let dropped = matched.filter(&!&keep)?;
+    // Claims are settled in ID order, but the kept pairs go back to the order `match_frames` uses
+    let matched = matched.filter(&keep)?.sort(
+        [
+            columns::DATE,
+            columns::EVENT,
+            columns::ID,
+            columns::ID_RIGHT,
+        ],
+        SortMultipleOptions::default(),
+    )?;
+
+    // Pull rows for files that lost every claim out of the outer join
+    let orphans = |column: &str, used: &HashSet<&PathBuf>| -> Result<DataFrame> {
+        let mut orphaned: HashSet<PathBuf> = get_df_column_as_paths(&dropped, column)?
+            .into_iter()
+            .filter(|p| !used.contains(p))
+            .collect();
+        let mask: BooleanChunked = frames
+            .joined
+            .column(column)?
+            .str()?
+            .into_iter()
+            .map(|p| p.is_some_and(|p| orphaned.remove(Path::new(p))))
+            .collect();
+        Ok(frames.joined.filter(&mask)?)
+    };
+    let unmatched_rgb = frames
+        .unmatched_rgb
+        .vstack(&orphans(columns::PATH, &used_rgb)?)?;
+    let unmatched_nir = frames
+        .unmatched_nir
+        .vstack(&orphans(columns::PATH_RIGHT, &used_nir)?)?;
+
+    let frames = MatchFrames {
+        matched,
+        joined: frames.joined,
+        unmatched_rgb,
+        unmatched_nir,
+    };
+    Ok((frames, dropped))
+}
+
+/// Compute the match between two band directories without moving anything.
+pub fn plan_images(rgb_dir: &Path, nir_dir: &Path) -> Result<MatchFrames> {
+    let rgb_df = make_iiq_df(&find_files(rgb_dir, &[".IIQ"])?)?;
+    let nir_df = make_iiq_df(&find_files(nir_dir, &[".IIQ"])?)?;
+    match_frames(&rgb_df, &nir_df)
+}
+
+/// Find the NIR file in `nir_dir` that pairs with a single RGB file. If several share its event,
+/// the one with the lowest ID is returned.
+pub fn match_one(rgb_file: &Path, nir_dir: &Path) -> Result<Option<PathBuf>> {
+    let rgb_df = make_iiq_df(&[rgb_file.to_path_buf()])?;
+    let nir_df = make_iiq_df(&find_files(nir_dir, &[".IIQ"])?)?;
+    let frames = match_frames(&rgb_df, &nir_df)?;
+    Ok(
+        get_df_column_as_paths(&frames.matched, columns::PATH_RIGHT)?
+            .into_iter()
+            .next(),
+    )
+}
+
+/// Files checked against a reference list of event numbers, such as a camera trigger log.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReferenceMatch {
+    /// Reference events with the files that have them.
+    pub matched: Vec<(String, PathBuf)>,
+    /// Reference events no file has.
+    pub missing: Vec<String>,
+    /// Files whose event isn't in the reference list.
+    pub unexpected: Vec<PathBuf>,
+}
+
+/// Match files against a list of expected event numbers instead of a second band.
+pub fn match_reference_events(files: &[PathBuf], events: &[String]) -> Result<ReferenceMatch> {
+    let reference_df = df!(columns::EVENT => events)?;
+    let file_df = make_iiq_df(files)?;
+    let matched = reference_df
+        .inner_join(&file_df, &[columns::EVENT], &[columns::EVENT])?
+        .sort([columns::EVENT, columns::ID], Default::default())?;
+    let joined = reference_df.outer_join(&file_df, &[columns::EVENT], &[columns::EVENT])?;
+
+    let missing = joined.filter(&joined.column(columns::PATH)?.is_null())?;
+    let unexpected = joined.filter(&joined.column(columns::EVENT)?.is_null())?;
+    let events = matched.column(columns::EVENT)?.str()?;
+    let paths = matched.column(columns::PATH)?.str()?;
+    Ok(ReferenceMatch {
+        matched: events
+            .into_iter()
+            .zip(paths)
+            .filter_map(|(event, path)| Some((event?.to_string(), PathBuf::from(path?))))
+            .collect(),
+        missing: missing
+            .column(columns::EVENT)?
+            .str()?
+            .into_iter()
+            .flatten()
+            .map(str::to_string)
+            .collect(),
+        unexpected: get_df_column_as_paths(&unexpected, columns::PATH)?,
+    })
+}
+
+/// Problems found by `validate`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    /// Files whose names don't follow the IIQ naming scheme.
+    pub malformed: Vec<PathBuf>,
+    /// Files dated before 2000 or after today.
+    pub out_of_range: Vec<PathBuf>,
+    /// Files with no content.
+    pub empty: Vec<PathBuf>,
+    /// Event numbers used by more than one RGB file.
+    pub duplicate_rgb_events: Vec<String>,
+    /// Event numbers used by more than one NIR file.
+    pub duplicate_nir_events: Vec<String>,
+}
+
+impl ValidationReport {
+    /// Whether no problems were found.
+    pub fn is_clean(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Check two band directories for files that would cause trouble when matching, without moving
+/// anything.
+pub fn validate(rgb_dir: &Path, nir_dir: &Path) -> Result<ValidationReport> {
+    let earliest = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+    let today = chrono::Local::now().date_naive();
+
+    let mut report = ValidationReport::default();
+    for (dir, is_rgb) in [(rgb_dir, true), (nir_dir, false)] {
+        let files = find_files(dir, &[".IIQ"])?;
+        let (_, empty) = split_by_size(&files, 1)?;
+        report.empty.extend(empty);
+
+        let (df, malformed) = make_iiq_df_lenient(&files)?;
+        report.malformed.extend(malformed);
+        let dates = df.column(columns::DATE)?.str()?;
+        let paths = df.column(columns::PATH)?.str()?;
+        for (date, path) in dates.into_iter().zip(paths) {
+            let (Some(date), Some(path)) = (date, path) else {
+                continue;
+            };
+            let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")?;
+            if date < earliest || date > today {
+                report.out_of_range.push(PathBuf::from(path));
+            }
+        }
+
+        let duplicates = find_duplicate_events(&df)?;
+        if is_rgb {
+            report.duplicate_rgb_events = duplicates;
+        } else {
+            report.duplicate_nir_events = duplicates;
+        }
+    }
+    Ok(report)
+}
+
+/// Settings shared by the `process_*` entry points.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcessOptions {
+    /// Name of the sub-directory unmatched files are moved into.
+    pub output_dir: String,
+    /// Report what would happen without touching any files.
+    pub dry_run: bool,
+    pub file_op: FileOp,
+    pub collision: CollisionPolicy,
+    /// Optional path to write a CSV manifest of matched pairs to.
+    pub manifest: Option<PathBuf>,
+    /// Drop pairs that reuse a file already claimed by another pair.
+    pub one_to_one: bool,
+    /// Ignore files dated before this day.
+    pub start: Option<NaiveDate>,
+    /// Ignore files dated after this day.
+    pub end: Option<NaiveDate>,
+    /// Treat files dated before this day as malformed, since their names are likely corrupt.
+    pub earliest_plausible: Option<NaiveDate>,
+    /// Treat files dated after this day as malformed, since their names are likely corrupt.
+    pub latest_plausible: Option<NaiveDate>,
+    /// Read the first bytes of each file and skip those that aren't IIQs.
+    pub check_signatures: bool,
+    /// Move files with unparseable names to `malformed_dir` instead of failing the run.
+    pub skip_malformed: bool,
+    /// Name of the sub-directory malformed files are moved into.
+    pub malformed_dir: String,
+    /// Number of characters before the date in each file name, for cameras that prefix their
+    /// names, e.g. 4 for `DSC_2024-05-01001_RGB_1.IIQ`.
+    pub name_prefix_len: usize,
+    /// How many levels of sub-directories to search for files. Zero searches only the band
+    /// directory itself.
+    pub max_depth: usize,
+    /// Move both files of every matched pair into this one directory.
+    pub combined_dir: Option<PathBuf>,
+    /// Prefix files moved to `combined_dir` with the number of their pair.
+    pub index_pairs: bool,
+    /// Put each pair in its own numbered sub-directory of `combined_dir`.
+    pub pair_subdirs: bool,
+    /// Move the RGB file of every matched pair into this directory, gathering them from any
+    /// sub-directories they were found in. Ignored when `combined_dir` is set.
+    pub matched_rgb_dir: Option<PathBuf>,
+    /// Move the NIR file of every matched pair into this directory. See `matched_rgb_dir`.
+    pub matched_nir_dir: Option<PathBuf>,
+    /// Only consider this many files from each band, taking the earliest first.
+    pub limit: Option<usize>,
+    /// Files smaller than this many bytes are moved to `undersized_dir` instead of being matched.
+    pub min_size: u64,
+    /// Name of the sub-directory undersized files are moved into.
+    pub undersized_dir: String,
+    /// Move undersized files from both bands into this one directory instead, prefixing their
+    /// names with `RGB_` or `NIR_`.
+    pub shared_undersized_dir: Option<PathBuf>,
+    /// Compare every copied file against its source and fail the run if they differ.
+    pub verify: bool,
+    /// How many times to retry a file transfer that fails with a transient error, such as a
+    /// timeout on a network share. Waits between attempts grow from 200 ms.
+    pub retries: u32,
+    /// How many files to transfer, and directories to search, at once. Values above one help on
+    /// fast storage, but report every failure instead of stopping at the first.
+    pub jobs: usize,
+    /// Recreate each file's sub-directory of the band directory under the directory it is moved
+    /// into, rather than putting all files side by side.
+    pub mirror_subdirs: bool,
+    /// Optional path to write a CSV record of every file moved, for use with `undo_run`.
+    pub moves_manifest: Option<PathBuf>,
+    /// After a real run, write a `match_manifest.csv` into each band directory and the combined
+    /// and matched directories so the sorted files document themselves. The manifests list each
+    /// file where it ended up, and are not written if the run is cancelled.
+    pub manifest_in_outputs: bool,
+    /// File extensions to match, compared case-insensitively. Any format works as long as the
+    /// names follow the IIQ naming scheme.
+    pub extensions: Vec<String>,
+    /// Before moving anything, try creating a file in every directory files will be moved into or
+    /// out of, failing with a list of those where that isn't possible. A dry run writes nothing,
+    /// so it only detects directories that are read-only for everyone.
+    pub check_writable: bool,
+    /// Glob patterns for names of sub-directories that are never searched for files, such as
+    /// `_thumbnails`.
+    pub ignore_dirs: Vec<String>,
+    /// Leave files that can't be opened where they are instead of failing the run.
+    pub skip_inaccessible: bool,
+    /// Pass over sub-directories that can't be read instead of failing the run. They are listed
+    /// in `ProcessReport::unreadable_dirs`.
+    pub skip_unreadable_dirs: bool,
+    /// Leave out hidden files and sub-directories, whose names start with a dot. Sync tools and
+    /// macOS create these next to the real images.
+    pub skip_hidden: bool,
+    /// Leave byte-identical copies of a file within a band out of matching, keeping the copy
+    /// whose name sorts first. The copies are not moved.
+    pub dedupe: bool,
+    /// Fail without moving anything if more than this fraction (0 to 1) of the files in both
+    /// bands are unmatched, which usually means the cameras fell out of sync.
+    pub max_unmatched_fraction: Option<f64>,
+    /// Warn about matched pairs whose RGB file is less than this many times the size of their
+    /// NIR file. They are still moved as usual and listed in `ProcessReport::size_mismatches`.
+    pub min_size_ratio: Option<f64>,
+    /// Warn about matched pairs whose RGB file is more than this many times the size of their
+    /// NIR file. See `min_size_ratio`.
+    pub max_size_ratio: Option<f64>,
+    /// Instead of moving anything, write the planned transfers to this path as a batch file on
+    /// Windows or a POSIX shell script elsewhere (see `write_move_script`). Implies `dry_run`.
+    pub move_script: Option<PathBuf>,
+    /// Change the case of file names as they are moved, e.g. for tools that expect `.iiq`.
+    /// Collisions are checked against the changed names.
+    pub name_case: Option<NameCase>,
+    /// Leave unmatched files where they are instead of moving them into `output_dir`, for runs
+    /// that are repeated while files are still arriving (see `watch`).
+    pub keep_unmatched: bool,
+    /// Token for stopping the run from another thread. Cancelling it stops the run before the
+    /// next file transfer, keeping those already made.
+    pub cancel: Option<CancelToken>,
+}
+
+impl Default for ProcessOptions {
+    fn default() -> Self {
+        Self {
+            output_dir: "Unmatched".to_string(),
+            dry_run: false,
+            file_op: FileOp::default(),
+            collision: CollisionPolicy::default(),
+            manifest: None,
+            one_to_one: false,
+            start: None,
+            end: None,
+            earliest_plausible: None,
+            latest_plausible: None,
+            check_signatures: false,
+            skip_malformed: false,
+            malformed_dir: "Malformed".to_string(),
+            name_prefix_len: 0,
+            max_depth: 0,
+            combined_dir: None,
+            index_pairs: false,
+            pair_subdirs: false,
+            matched_rgb_dir: None,
+            matched_nir_dir: None,
+            limit: None,
+            min_size: 0,
+            undersized_dir: "Undersized".to_string(),
+            shared_undersized_dir: None,
+            verify: false,
+            retries: 0,
+            jobs: 1,
+            mirror_subdirs: false,
+            moves_manifest: None,
+            manifest_in_outputs: false,
+            extensions: vec![".IIQ".to_string()],
+            check_writable: false,
+            ignore_dirs: Vec::new(),
+            skip_inaccessible: false,
+            skip_unreadable_dirs: false,
+            skip_hidden: false,
+            dedupe: false,
+            max_unmatched_fraction: None,
+            min_size_ratio: None,
+            max_size_ratio: None,
+            move_script: None,
+            name_case: None,
+            keep_unmatched: false,
+            cancel: None,
+        }
+    }
+}
+
+/// Summary of a single `process_images` run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProcessReport {
+    pub rgb_total: usize,
+    pub nir_total: usize,
+    pub matched: usize,
+    pub unmatched_rgb: usize,
+    pub unmatched_nir: usize,
+    /// Pairs that reused an already matched file.
+    pub duplicate_claims: usize,
+    /// Matched pairs whose file sizes are implausibly different.
+    pub size_mismatches: Vec<MatchedPair>,
+    /// Files skipped because their contents don't look like an IIQ.
+    pub invalid_signatures: usize,
+    /// Files whose names couldn't be parsed.
+    pub malformed: usize,
+    /// Files smaller than the minimum size.
+    pub undersized: usize,
+    /// Files skipped because their paths aren't valid UTF-8.
+    pub non_utf8: usize,
+    /// Files skipped because they couldn't be opened.
+    pub inaccessible: Vec<PathBuf>,
+    /// Sub-directories that were not searched because they couldn't be read.
+    pub unreadable_dirs: Vec<PathBuf>,
+    /// Files left out of matching because they are byte-identical copies of another file.
+    pub duplicate_files: Vec<PathBuf>,
+    /// Event numbers used by more than one RGB file.
+    pub duplicate_rgb_events: Vec<String>,
+    /// Event numbers used by more than one NIR file.
+    pub duplicate_nir_events: Vec<String>,
+    /// Bytes of matched files transferred into the combined or matched directories.
+    pub bytes_matched: u64,
+    /// Bytes transferred into the unmatched directories.
+    pub bytes_unmatched: u64,
+    /// Bytes transferred into the undersized directories.
+    pub bytes_undersized: u64,
+    /// Bytes transferred into the malformed directories.
+    pub bytes_malformed: u64,
+    /// Date of the earliest matched pair, if any matched.
+    pub first_capture: Option<NaiveDate>,
+    /// Date of the latest matched pair, if any matched.
+    pub last_capture: Option<NaiveDate>,
+    /// Time between the first and last capture. File names only carry the date, so this is a
+    /// whole number of days.
+    pub span: chrono::TimeDelta,
+    /// Whether the run was cancelled through `ProcessOptions::cancel` before every file was
+    /// transferred. The transfers made so far are kept and listed in `moves`, so they can be
+    /// reversed with `undo_moves`.
+    pub cancelled: bool,
+    /// Every file transfer made, in order. In a dry run, the transfers that would have been made.
+    pub moves: Vec<FileMove>,
+}
+
+/// Match the IIQ files found in `rgb_dir` and `nir_dir` by event and move the unmatched ones
+/// aside as configured by `options`. Fails if both paths resolve to the same directory. Files are
+/// discovered with the options' extensions, depth and exclusions, and the returned
+/// `ProcessReport` describes what was matched and moved.
+pub fn process_images(
+    rgb_dir: &Path,
+    nir_dir: &Path,
+    options: &ProcessOptions,
+) -> Result<ProcessReport> {
+    let canonical = |dir: &Path| {
+        dir.canonicalize()
+            .with_context(|| format!("Failed to resolve {}", dir.display()))
+    };
+    if canonical(rgb_dir)? == canonical(nir_dir)? {
+        bail!(IxMatchError::SameDirectory(rgb_dir.to_path_buf()));
+    }
+
+    let (rgb_iiq_files, rgb_unreadable) = find_option_files(rgb_dir, options)?;
+    let (nir_iiq_files, nir_unreadable) = find_option_files(nir_dir, options)?;
+
+    let mut report = process_file_lists(rgb_dir, nir_dir, &rgb_iiq_files, &nir_iiq_files, options)?;
+    report.unreadable_dirs = [rgb_unreadable, nir_unreadable].concat();
+    Ok(report)
+}
+
+/// Find the RGB and NIR directories in a session folder with `find_dirs_by_pattern`, then process
+/// them with `process_images`. Fails if either pattern matches no directory or more than one.
+pub fn process_session(
+    root: &Path,
+    rgb_pattern: &str,
+    nir_pattern: &str,
+    options: &ProcessOptions,
+) -> Result<ProcessReport> {
+    let find = |pattern: &str| -> Result<PathBuf> {
+        let mut dirs = find_dirs_by_pattern(root, pattern)?;
+        match dirs.len() {
+            1 => Ok(dirs.remove(0)),
+            0 => bail!(IxMatchError::DirectoryNotFound {
+                pattern: pattern.to_string(),
+                base: root.to_path_buf(),
+            }),
+            _ => bail!(IxMatchError::AmbiguousDirectory {
+                pattern: pattern.to_string(),
+                base: root.to_path_buf(),
+                matches: dirs,
+            }),
+        }
+    };
+    process_images(&find(rgb_pattern)?, &find(nir_pattern)?, options)
+}
+
+/// Match files in `rgb_dir` and `nir_dir` as they arrive, for example while a card is still
+/// being copied. Both directories are searched every `interval`, and a file is only considered
+/// once its size and modification time haven't changed since the previous search, so files that
+/// are still being written are left alone. Pairs are transferred as soon as both files are
+/// complete, while unmatched files stay in place. Once no file has appeared or changed for
+/// `idle`, a final pass handles the files left over as `options` asks, moving unmatched files
+/// unless `options.keep_unmatched` is set. Cancelling `options.cancel` stops watching without the
+/// final pass.
+///
+/// Matched pairs have to leave the band directories, so `options.combined_dir` or both matched
+/// directories must be set. Dry runs, move scripts and match manifests describe a single run and
+/// are not supported. Returns the report of the final pass, with `moves` listing the transfers of
+/// every pass, which are also what `options.moves_manifest` records.
+pub fn watch(
+    rgb_dir: &Path,
+    nir_dir: &Path,
+    options: &ProcessOptions,
+    interval: std::time::Duration,
+    idle: std::time::Duration,
+) -> Result<ProcessReport> {
+    if options.combined_dir.is_none()
+        && (options.matched_rgb_dir.is_none() || options.matched_nir_dir.is_none())
+    {
+        bail!("Watching needs a combined directory or both matched directories");
+    }
+    if options.dry_run || options.move_script.is_some() {
+        bail!("Watching moves files as they arrive and can't be combined with a dry run");
+    }
+    if options.manifest.is_some() || options.manifest_in_outputs {
+        bail!("Watching can't write a match manifest");
+    }
+    if rgb_dir.canonicalize()? == nir_dir.canonicalize()? {
+        bail!(IxMatchError::SameDirectory(rgb_dir.to_path_buf()));
+    }
+    let cancelled = || {
+        options
+            .cancel
+            .as_ref()
+            .is_some_and(CancelToken::is_cancelled)
+    };
+    let pass_options = ProcessOptions {
+        keep_unmatched: true,
+        // Pairs whose second file hasn't arrived yet are expected
+        max_unmatched_fraction: None,
+        moves_manifest: None,
+        ..options.clone()
+    };
+
+    info!("Watching {} and {}", rgb_dir.display(), nir_dir.display());
+    let mut moves: Vec<FileMove> = Vec::new();
+    // Sources already transferred, which a copy or link leaves in place
+    let mut transferred: HashSet<PathBuf> = HashSet::new();
+    let mut seen: std::collections::HashMap<PathBuf, (u64, Option<std::time::SystemTime>)> =
+        std::collections::HashMap::new();
+    let mut processed = (Vec::new(), Vec::new());
+    let mut last_change = std::time::Instant::now();
+    while !cancelled() {
+        let (rgb_files, _) = find_option_files(rgb_dir, &pass_options)?;
+        let (nir_files, _) = find_option_files(nir_dir, &pass_options)?;
+        let mut current = std::collections::HashMap::new();
+        let mut settle = |files: Vec<PathBuf>| -> Vec<PathBuf> {
+            files
+                .into_iter()
+                .filter(|path| !transferred.contains(path))
+                .filter(|path| {
+                    let Ok(metadata) = std::fs::metadata(path) else {
+                        return false;
+                    };
+                    let state = (metadata.len(), metadata.modified().ok());
+                    let settled = seen.get(path) == Some(&state);
+                    current.insert(path.clone(), state);
+                    settled
+                })
+                .collect()
+        };
+        let settled = (settle(rgb_files), settle(nir_files));
+        if current != seen {
+            last_change = std::time::Instant::now();
+        }
+        seen = current;
+
+        if settled != processed && (!settled.0.is_empty() || !settled.1.is_empty()) {
+            let report =
+                process_file_lists(rgb_dir, nir_dir, &settled.0, &settled.1, &pass_options)?;
+            transferred.extend(report.moves.iter().map(|m| m.source.clone()));
+            moves.extend(report.moves);
+            let untouched = |files: Vec<PathBuf>| -> Vec<PathBuf> {
+                files
+                    .into_iter()
+                    .filter(|path| !transferred.contains(path))
+                    .collect()
+            };
+            processed = (untouched(settled.0), untouched(settled.1));
+        } else if last_change.elapsed() >= idle {
+            break;
+        }
+        std::thread::sleep(interval);
+    }
+
+    let mut report = if cancelled() {
+        warn!("Cancelled after {} transfers", moves.len());
+        ProcessReport {
+            cancelled: true,
+            ..Default::default()
+        }
+    } else {
+        info!("No new files for {:?}, finishing", idle);
+        let final_options = ProcessOptions {
+            moves_manifest: None,
+            ..options.clone()
+        };
+        let (rgb_files, rgb_unreadable) = find_option_files(rgb_dir, &final_options)?;
+        let (nir_files, nir_unreadable) = find_option_files(nir_dir, &final_options)?;
+        let remaining = |files: Vec<PathBuf>| -> Vec<PathBuf> {
+            files
+                .into_iter()
+                .filter(|path| !transferred.contains(path))
+                .collect()
+        };
+        let (rgb_files, nir_files) = (remaining(rgb_files), remaining(nir_files));
+        // A band emptied by the pairs transferred while watching isn't a missing band
+        let mut report = process_lists(
+            rgb_dir,
+            nir_dir,
+            &rgb_files,
+            &nir_files,
+            &final_options,
+            transferred.is_empty(),
+        )?;
+        report.unreadable_dirs = [rgb_unreadable, nir_unreadable].concat();
+        report
+    };
+    moves.append(&mut report.moves);
+    report.moves = moves;
+
+    if let Some(path) = &options.moves_manifest {
+        write_moves_manifest(&report.moves, options.file_op, path)?;
+        info!("Wrote moves manifest to {}", path.display());
+    }
+    Ok(report)
+}
+
+/// Band predicate for `process_single_dir` that accepts files whose image ID leaves `remainder`
+/// when divided by `modulus`, for cameras that write both bands into one directory with
+/// alternating IDs. Names are parsed after skipping `prefix_len` characters, as with
+/// `ProcessOptions::name_prefix_len`; files whose names can't be parsed are not accepted.
+pub fn id_modulo(
+    modulus: i32,
+    remainder: i32,
+    prefix_len: usize,
+) -> Result<impl Fn(&Path) -> bool> {
+    if modulus <= 0 || !(0..modulus).contains(&remainder) {
+        bail!(
+            "ID remainder {} must be at least 0 and less than the modulus {}",
+            remainder,
+            modulus
+        );
+    }
+    Ok(move |path: &Path| {
+        iiq_filename(path, prefix_len).is_ok_and(|name| name.id.rem_euclid(modulus) == remainder)
+    })
+}
+
+/// Match RGB and NIR files that share a single directory. Each file is assigned to a band with
+/// the `is_rgb` and `is_nir` predicates; files matching neither are reported and ignored.
+pub fn process_single_dir(
+    dir: &Path,
+    is_rgb: impl Fn(&Path) -> bool,
+    is_nir: impl Fn(&Path) -> bool,
+    options: &ProcessOptions,
+) -> Result<ProcessReport> {
+    let mut rgb_iiq_files = Vec::new();
+    let mut nir_iiq_files = Vec::new();
+    let (files, unreadable) = find_option_files(dir, options)?;
+    for path in files {
+        match (is_rgb(&path), is_nir(&path)) {
+            (true, true) => bail!("{} matches both RGB and NIR", path.display()),
+            (true, false) => rgb_iiq_files.push(path),
+            (false, true) => nir_iiq_files.push(path),
+            (false, false) => warn!("{} matches neither RGB nor NIR, skipping", path.display()),
+        }
+    }
+
+    let mut report = process_file_lists(dir, dir, &rgb_iiq_files, &nir_iiq_files, options)?;
+    report.unreadable_dirs = unreadable;
+    Ok(report)
+}
+
+/// Discover IIQ files in `dir`, skipping the sub-directories previous runs sorted files into
+/// (including the configured output directories if they lie inside `dir`) and any matching
+/// `options.ignore_dirs`. Also returns the sub-directories skipped as unreadable.
+fn find_option_files(dir: &Path, options: &ProcessOptions) -> Result<(Vec<PathBuf>, Vec<PathBuf>)> {
+    let mut exclude: Vec<String> = [
+        &options.output_dir,
+        &options.malformed_dir,
+        &options.undersized_dir,
+    ]
+    .into_iter()
+    .map(|name| glob::Pattern::escape(name))
+    .collect();
+    for pattern in &options.ignore_dirs {
+        glob::Pattern::new(pattern)
+            .with_context(|| format!("Invalid ignore pattern '{}'", pattern))?;
+        exclude.push(pattern.clone());
+    }
+    let exclude: Vec<&str> = exclude.iter().map(String::as_str).collect();
+    let extensions: Vec<&str> = options.extensions.iter().map(String::as_str).collect();
+    let output_dirs: Vec<PathBuf> = [
+        &options.combined_dir,
+        &options.matched_rgb_dir,
+        &options.matched_nir_dir,
+        &options.shared_undersized_dir,
+    ]
+    .into_iter()
+    .flatten()
+    .cloned()
+    .collect();
+    let mut found = find_files_iter(dir, &extensions, options.max_depth, &exclude)
+        .skip_unreadable(options.skip_unreadable_dirs)
+        .skip_hidden(options.skip_hidden)
+        .skip_dirs(&output_dirs);
+    let files = if options.jobs > 1 {
+        found.collect_parallel(options.jobs)?
+    } else {
+        found.by_ref().collect::<Result<Vec<PathBuf>>>()?
+    };
+    for skipped in found.skipped() {
+        warn!("Skipping unreadable directory {}", skipped.display());
+    }
+    Ok((files, found.skipped().to_vec()))
+}
+
+/// Like `process_images`, but for files that have already been discovered. Files set aside are
+/// still moved into sub-directories of `rgb_dir` and `nir_dir`.
+pub fn process_file_lists(
+    rgb_dir: &Path,
+    nir_dir: &Path,
+    rgb_iiq_files: &[PathBuf],
+    nir_iiq_files: &[PathBuf],
+    options: &ProcessOptions,
+) -> Result<ProcessReport> {
+    process_lists(
+        rgb_dir,
+        nir_dir,
+        rgb_iiq_files,
+        nir_iiq_files,
+        options,
+        true,
+    )
+}
+
+/// `process_file_lists`, optionally matching even when a band has no files, in which case every
+/// file of the other band is unmatched.
+fn process_lists(
+    rgb_dir: &Path,
+    nir_dir: &Path,
+    rgb_iiq_files: &[PathBuf],
+    nir_iiq_files: &[PathBuf],
+    options: &ProcessOptions,
+    require_both_bands: bool,
+) -> Result<ProcessReport> {
+    // A move script only records the plan, so nothing may be touched
+    let script_options;
+    let options = if options.move_script.is_some() && !options.dry_run {
+        script_options = ProcessOptions {
+            dry_run: true,
+            ..options.clone()
+        };
+        &script_options
+    } else {
+        options
+    };
+
+    // Without both bands there is nothing to match, and every file would be moved aside
+    if require_both_bands && (rgb_iiq_files.is_empty() || nir_iiq_files.is_empty()) {
+        for (dir, files) in [(rgb_dir, rgb_iiq_files), (nir_dir, nir_iiq_files)] {
+            if files.is_empty() {
+                warn!("No IIQ files found in {}", dir.display());
+            }
+        }
+
+        // Still write the files asked for, so their absence doesn't look like a failure
+        if let Some(manifest) = &options.manifest {
+            let no_pairs: Vec<String> = Vec::new();
+            let matched = df!(
+                columns::DATE => &no_pairs,
+                columns::EVENT => &no_pairs,
+                columns::PATH => &no_pairs,
+                columns::PATH_RIGHT => &no_pairs,
+            )?;
+            write_match_manifest(&matched, manifest)?;
+        }
+        if let Some(manifest) = &options.moves_manifest {
+            write_moves_manifest(&[], options.file_op, manifest)?;
+        }
+        if let Some(script) = &options.move_script {
+            write_move_script(&[], options.file_op, script)?;
+        }
+
+        return Ok(ProcessReport {
+            rgb_total: rgb_iiq_files.len(),
+            nir_total: nir_iiq_files.len(),
+            unmatched_rgb: rgb_iiq_files.len(),
+            unmatched_nir: nir_iiq_files.len(),
+            ..Default::default()
+        });
+    }
+
+    // Paths go through string columns, so only UTF-8 ones survive the round trip
+    let (rgb_iiq_files, non_utf8_rgb): (Vec<PathBuf>, Vec<PathBuf>) = rgb_iiq_files
+        .iter()
+        .cloned()
+        .partition(|p| p.to_str().is_some());
+    let (nir_iiq_files, non_utf8_nir): (Vec<PathBuf>, Vec<PathBuf>) = nir_iiq_files
+        .iter()
+        .cloned()
+        .partition(|p| p.to_str().is_some());
+    for path in non_utf8_rgb.iter().chain(&non_utf8_nir) {
+        warn!("{} is not valid UTF-8, skipping", path.display());
+    }
+
+    let mut inaccessible = Vec::new();
+    let (rgb_iiq_files, nir_iiq_files) = if options.skip_inaccessible {
+        let (rgb_accessible, rgb_inaccessible) = split_accessible(&rgb_iiq_files);
+        let (nir_accessible, nir_inaccessible) = split_accessible(&nir_iiq_files);
+        inaccessible.extend(rgb_inaccessible);
+        inaccessible.extend(nir_inaccessible);
+        (rgb_accessible, nir_accessible)
+    } else {
+        (rgb_iiq_files, nir_iiq_files)
+    };
+
+    let (rgb_iiq_files, undersized_rgb) = split_by_size(&rgb_iiq_files, options.min_size)?;
+    let (nir_iiq_files, undersized_nir) = split_by_size(&nir_iiq_files, options.min_size)?;
+
+    let mut invalid_signatures = 0;
+    let (rgb_iiq_files, nir_iiq_files) = if options.check_signatures {
+        let (rgb_valid, rgb_invalid) = validate_signatures(&rgb_iiq_files)?;
+        let (nir_valid, nir_invalid) = validate_signatures(&nir_iiq_files)?;
+        for path in rgb_invalid.iter().chain(&nir_invalid) {
+            warn!("{} is not a valid IIQ file, skipping", path.display());
+        }
+        invalid_signatures = rgb_invalid.len() + nir_invalid.len();
+        (rgb_valid, nir_valid)
+    } else {
+        (rgb_iiq_files, nir_iiq_files)
+    };
+
+    let mut duplicate_files = Vec::new();
+    let (rgb_iiq_files, nir_iiq_files) = if options.dedupe {
+        let (rgb_unique, rgb_duplicates) = split_duplicate_files(&rgb_iiq_files)?;
+        let (nir_unique, nir_duplicates) = split_duplicate_files(&nir_iiq_files)?;
+        duplicate_files.extend(rgb_duplicates);
+        duplicate_files.extend(nir_duplicates);
+        (rgb_unique, nir_unique)
+    } else {
+        (rgb_iiq_files, nir_iiq_files)
+    };
+
+    // Create dataframes
+    let make_df = |files: &[PathBuf]| {
+        if options.skip_malformed {
+            make_prefixed_iiq_df_lenient(files, options.name_prefix_len)
+        } else {
+            make_prefixed_iiq_df(files, options.name_prefix_len).map(|df| (df, Vec::new()))
+        }
+    };
+    let (rgb_df, malformed_rgb) = make_df(&rgb_iiq_files)?;
+    let (nir_df, malformed_nir) = make_df(&nir_iiq_files)?;
+    let (rgb_df, malformed_rgb) = reject_implausible_dates(rgb_df, malformed_rgb, options)?;
+    let (nir_df, malformed_nir) = reject_implausible_dates(nir_df, malformed_nir, options)?;
+    let rgb_df = filter_by_date(&rgb_df, options.start, options.end)?;
+    let nir_df = filter_by_date(&nir_df, options.start, options.end)?;
+    let (rgb_df, nir_df) = match options.limit {
+        Some(limit) => (first_files(&rgb_df, limit)?, first_files(&nir_df, limit)?),
+        None => (rgb_df, nir_df),
+    };
+
+    let duplicate_rgb_events = find_duplicate_events(&rgb_df)?;
+    let duplicate_nir_events = find_duplicate_events(&nir_df)?;
+
+    let frames = match_frames(&rgb_df, &nir_df)?;
+    let (enforced, dropped) = enforce_one_to_one(frames.clone())?;
+    let frames = if options.one_to_one { enforced } else { frames };
+    let result = MatchResult::from_frames(&frames)?;
+    info!("Found IIQs!");
+    info!(
+        "RGB: {}, NIR: {} ({} match)",
+        rgb_df.height(),
+        nir_df.height(),
+        result.matched.len()
+    );
+
+    for (band, events) in [
+        ("RGB", &duplicate_rgb_events),
+        ("NIR", &duplicate_nir_events),
+    ] {
+        for event in events {
+            warn!("Event {} appears in more than one {} file", event, band);
+        }
+    }
+
+    let dropped_rgb = get_df_column_as_paths(&dropped, columns::PATH)?;
+    let dropped_nir = get_df_column_as_paths(&dropped, columns::PATH_RIGHT)?;
+    for (rgb, nir) in dropped_rgb.iter().zip(&dropped_nir) {
+        warn!(
+            "Duplicate claim: {} and {} reuse an already matched file",
+            rgb.display(),
+            nir.display()
+        );
+    }
+
+    let size_mismatches = find_size_mismatches(
+        &result.matched,
+        options.min_size_ratio,
+        options.max_size_ratio,
+    )?;
+    for pair in &size_mismatches {
+        warn!(
+            "Sizes of {} and {} differ more than expected",
+            pair.rgb.display(),
+            pair.nir.display()
+        );
+    }
+
+    if let Some(max_fraction) = options.max_unmatched_fraction {
+        let total = rgb_df.height() + nir_df.height();
+        let unmatched = result.unmatched_rgb.len() + result.unmatched_nir.len();
+        let fraction = unmatched as f64 / total.max(1) as f64;
+        if fraction > max_fraction {
+            bail!(
+                "{} of {} files ({:.1}%) are unmatched, more than the {:.1}% allowed",
+                unmatched,
+                total,
+                fraction * 100.0,
+                max_fraction * 100.0
+            );
+        }
+    }
+
+    if let Some(manifest) = &options.manifest {
+        write_match_manifest(&frames.matched, manifest)?;
+        info!("Wrote match manifest to {}", manifest.display());
+    }
+
+    if result.unmatched_rgb.is_empty() && result.unmatched_nir.is_empty() {
+        info!("All files matched!");
+    } else if options.keep_unmatched {
+        info!(
+            "Leaving {} unmatched files in place",
+            result.unmatched_rgb.len() + result.unmatched_nir.len()
+        );
+    } else {
+        info!(
+            "{} unmatched files to '{}/' sub-directories",
+            options.file_op.verb(),
+            options.output_dir
+        );
+    }
+
+    if !malformed_rgb.is_empty() || !malformed_nir.is_empty() {
+        info!(
+            "{} files with malformed names to '{}/' sub-directories",
+            options.file_op.verb(),
+            options.malformed_dir
+        );
+    }
+
+    let mut batches = Vec::new();
+    let mut band_batches = Vec::new();
+    if !undersized_rgb.is_empty() || !undersized_nir.is_empty() {
+        if let Some(shared_dir) = &options.shared_undersized_dir {
+            info!(
+                "{} files smaller than {} bytes to {}",
+                options.file_op.verb(),
+                options.min_size,
+                shared_dir.display()
+            );
+            let files = [("NIR", &undersized_nir), ("RGB", &undersized_rgb)]
+                .into_iter()
+                .flat_map(|(band, files)| files.iter().map(move |path| (band, path)))
+                .map(|(band, path)| {
+                    let name = path.file_name().unwrap().to_string_lossy();
+                    (path.clone(), format!("{}_{}", band, name).into())
+                })
+                .collect();
+            batches.push((shared_dir.clone(), files));
+        } else {
+            info!(
+                "{} files smaller than {} bytes to '{}/' sub-directories",
+                options.file_op.verb(),
+                options.min_size,
+                options.undersized_dir
+            );
+            band_batches.push((nir_dir, &options.undersized_dir, &undersized_nir));
+            band_batches.push((rgb_dir, &options.undersized_dir, &undersized_rgb));
+        }
+    }
+    if !options.keep_unmatched {
+        band_batches.push((nir_dir, &options.output_dir, &result.unmatched_nir));
+        band_batches.push((rgb_dir, &options.output_dir, &result.unmatched_rgb));
+    }
+    band_batches.extend([
+        (nir_dir, &options.malformed_dir, &malformed_nir),
+        (rgb_dir, &options.malformed_dir, &malformed_rgb),
+    ]);
+    for (band_dir, subdir, files) in band_batches {
+        let dest_dir = band_dir.join(subdir);
+        if options.mirror_subdirs {
+            batches.extend(mirror_batches(band_dir, &dest_dir, files));
+        } else {
+            batches.push((dest_dir, keep_names(files)));
+        }
+    }
+    if let Some(combined_dir) = &options.combined_dir {
+        info!(
+            "{} matched pairs to {}",
+            options.file_op.verb(),
+            combined_dir.display()
+        );
+        if options.pair_subdirs {
+            for (i, pair) in result.matched.iter().enumerate() {
+                let pair_dir = combined_dir.join(format!("pair_{:04}", i + 1));
+                batches.push((pair_dir, keep_names(&[pair.rgb.clone(), pair.nir.clone()])));
+            }
+        } else {
+            let files = result
+                .matched
+                .iter()
+                .enumerate()
+                .flat_map(|(i, pair)| [(i, &pair.rgb), (i, &pair.nir)])
+                .map(|(i, path)| {
+                    let name = path.file_name().unwrap();
+                    let name = if options.index_pairs {
+                        format!("{:04}_{}", i + 1, name.to_string_lossy()).into()
+                    } else {
+                        name.to_os_string()
+                    };
+                    (path.clone(), name)
+                })
+                .collect();
+            batches.push((combined_dir.clone(), files));
+        }
+    } else {
+        let matched_rgb: Vec<PathBuf> = result.matched.iter().map(|p| p.rgb.clone()).collect();
+        let matched_nir: Vec<PathBuf> = result.matched.iter().map(|p| p.nir.clone()).collect();
+        for (matched_dir, files) in [
+            (&options.matched_rgb_dir, matched_rgb),
+            (&options.matched_nir_dir, matched_nir),
+        ] {
+            if let Some(matched_dir) = matched_dir {
+                info!(
+                    "{} matched files to {}",
+                    options.file_op.verb(),
+                    matched_dir.display()
+                );
+                batches.push((matched_dir.clone(), keep_names(&files)));
+            }
+        }
+    }
+
+    if options.check_writable {
+        let mut dirs = std::collections::BTreeSet::new();
+        for (dest_dir, files) in batches.iter().filter(|(_, files)| !files.is_empty()) {
+            dirs.insert(dest_dir.clone());
+            if options.file_op == FileOp::Move {
+                dirs.extend(
+                    files
+                        .iter()
+                        .filter_map(|(path, _)| path.parent())
+                        .map(Path::to_path_buf),
+                );
+            }
+        }
+        let unwritable: Vec<String> = dirs
+            .iter()
+            .filter(|dir| !is_writable(dir, options.dry_run))
+            .map(|dir| dir.display().to_string())
+            .collect();
+        if !unwritable.is_empty() {
+            bail!("Cannot write to: {}", unwritable.join(", "));
+        }
+    }
+
+    let transfer = Transfer {
+        dry_run: options.dry_run,
+        file_op: options.file_op,
+        collision: options.collision,
+        verify: options.verify,
+        retries: options.retries,
+        jobs: options.jobs,
+        cancel: options.cancel.as_ref(),
+        name_case: options.name_case,
+    };
+    let mut moves = Vec::new();
+    for (dest_dir, files) in batches {
+        if files.is_empty() {
+            continue;
+        }
+        let moved = move_named_files(&files, &dest_dir, transfer, None);
+        match moved {
+            Ok(moved) => moves.extend(moved),
+            Err(e) => return Err(rollback(&moves, options.file_op, options.dry_run, e)),
+        }
+        if transfer.is_cancelled() {
+            break;
+        }
+    }
+    let cancelled = transfer.is_cancelled();
+    if cancelled {
+        warn!("Cancelled after {} transfers", moves.len());
+    }
+
+    if let Some(manifest) = &options.moves_manifest {
+        write_moves_manifest(&moves, options.file_op, manifest)?;
+        info!("Wrote moves manifest to {}", manifest.display());
+    }
+
+    if let Some(script) = &options.move_script {
+        write_move_script(&moves, options.file_op, script)?;
+        info!("Wrote move script to {}", script.display());
+    }
+
+    if options.manifest_in_outputs && cancelled {
+        warn!("Not writing match manifests into the output directories of a cancelled run");
+    } else if options.manifest_in_outputs && !options.dry_run {
+        // List each file where it ended up, not where it was found
+        let placed: std::collections::HashMap<&PathBuf, &PathBuf> =
+            moves.iter().map(|m| (&m.source, &m.destination)).collect();
+        let placed_paths = |band: fn(&MatchedPair) -> &PathBuf| -> Vec<String> {
+            result
+                .matched
+                .iter()
+                .map(|pair| {
+                    let path = band(pair);
+                    placed
+                        .get(path)
+                        .copied()
+                        .unwrap_or(path)
+                        .to_string_lossy()
+                        .into_owned()
+                })
+                .collect()
+        };
+        let matched = df!(
+            columns::DATE => result.matched.iter().map(|p| p.date.clone()).collect::<Vec<_>>(),
+            columns::EVENT => result.matched.iter().map(|p| p.event.clone()).collect::<Vec<_>>(),
+            columns::PATH => placed_paths(|pair| &pair.rgb),
+            columns::PATH_RIGHT => placed_paths(|pair| &pair.nir),
+        )?;
+
+        let mut dirs = vec![rgb_dir.to_path_buf(), nir_dir.to_path_buf()];
+        dirs.extend(options.combined_dir.clone());
+        dirs.extend(options.matched_rgb_dir.clone());
+        dirs.extend(options.matched_nir_dir.clone());
+        dirs.dedup();
+        for dir in dirs {
+            write_match_manifest(&matched, &dir.join("match_manifest.csv"))?;
+        }
+    }
+
+    // In a dry run nothing has moved yet, so the sources still hold the data
+    let bytes_moved = |groups: &[&[PathBuf]]| -> u64 {
+        let sources: HashSet<&PathBuf> = groups.iter().flat_map(|files| files.iter()).collect();
+        moves
+            .iter()
+            .filter(|m| sources.contains(&m.source))
+            .map(|m| {
+                if options.dry_run {
+                    &m.source
+                } else {
+                    &m.destination
+                }
+            })
+            .filter_map(|path| std::fs::metadata(path).ok())
+            .map(|metadata| metadata.len())
+            .sum()
+    };
+    let matched_files: Vec<PathBuf> = result
+        .matched
+        .iter()
+        .flat_map(|pair| [pair.rgb.clone(), pair.nir.clone()])
+        .collect();
+    let bytes_matched = bytes_moved(&[&matched_files]);
+    let bytes_unmatched = bytes_moved(&[&result.unmatched_rgb, &result.unmatched_nir]);
+    let bytes_undersized = bytes_moved(&[&undersized_rgb, &undersized_nir]);
+    let bytes_malformed = bytes_moved(&[&malformed_rgb, &malformed_nir]);
+
+    let capture_dates: Vec<NaiveDate> = result
+        .matched
+        .iter()
+        .filter_map(|pair| NaiveDate::parse_from_str(&pair.date, "%Y-%m-%d").ok())
+        .collect();
+    let first_capture = capture_dates.iter().min().copied();
+    let last_capture = capture_dates.iter().max().copied();
+    let span = match (first_capture, last_capture) {
+        (Some(first), Some(last)) => last - first,
+        _ => chrono::TimeDelta::zero(),
+    };
+
+    Ok(ProcessReport {
+        rgb_total: rgb_df.height(),
+        nir_total: nir_df.height(),
+        matched: result.matched.len(),
+        unmatched_rgb: result.unmatched_rgb.len(),
+        unmatched_nir: result.unmatched_nir.len(),
+        duplicate_claims: dropped.height(),
+        size_mismatches,
+        invalid_signatures,
+        malformed: malformed_rgb.len() + malformed_nir.len(),
+        undersized: undersized_rgb.len() + undersized_nir.len(),
+        non_utf8: non_utf8_rgb.len() + non_utf8_nir.len(),
+        inaccessible,
+        unreadable_dirs: Vec::new(),
+        duplicate_files,
+        duplicate_rgb_events,
+        duplicate_nir_events,
+        bytes_matched,
+        bytes_unmatched,
+        bytes_undersized,
+        bytes_malformed,
+        first_capture,
+        last_capture,
+        span,
+        cancelled,
+        moves,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A scratch directory removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+            Self::new_in(&std::env::temp_dir(), COUNT.fetch_add(1, Ordering::Relaxed))
+        }
+
+        fn new_in(parent: &Path, n: usize) -> Self {
+            let dir = parent.join(format!("ix-match-test-{}-{}", std::process::id(), n));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Create RGB files for events 001-004 and NIR files for events 002-005 under `root`, each
+    /// holding its own name, and return the band directories.
+    fn survey(root: &Path) -> (PathBuf, PathBuf) {
+        let rgb_dir = root.join("YC01");
+        let nir_dir = root.join("YD01");
+        std::fs::create_dir_all(&rgb_dir).unwrap();
+        std::fs::create_dir_all(&nir_dir).unwrap();
+        for event in 1..=4 {
+            let name = format!("2024-05-01{:03}_RGB_{}.IIQ", event, 1000 + event);
+            std::fs::write(rgb_dir.join(&name), &name).unwrap();
+        }
+        for event in 2..=5 {
+            let name = format!("2024-05-01{:03}_NIR_{}.IIQ", event, 2000 + event);
+            std::fs::write(nir_dir.join(&name), &name).unwrap();
+        }
+        (rgb_dir, nir_dir)
+    }
+
+    /// Every file and link under `root`, relative to it, with whether it is a link and what it
+    /// holds. Empty directories are left out.
+    fn layout(root: &Path) -> Vec<(PathBuf, bool, String)> {
+        let mut files = Vec::new();
+        let mut dirs = vec![root.to_path_buf()];
+        while let Some(dir) = dirs.pop() {
+            for entry in std::fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                let file_type = std::fs::symlink_metadata(&path).unwrap().file_type();
+                if file_type.is_dir() {
+                    dirs.push(path);
+                } else {
+                    let contents = std::fs::read_to_string(&path).unwrap_or_default();
+                    let relative = path.strip_prefix(root).unwrap().to_path_buf();
+                    files.push((relative, file_type.is_symlink(), contents));
+                }
+            }
+        }
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn apply_manifest_matches_direct_run() {
+        let planned = TempDir::new();
+        let direct = TempDir::new();
+        let plan = planned.0.join("plan.csv");
+        let run = |root: &Path, options: ProcessOptions| {
+            let (rgb_dir, nir_dir) = survey(root);
+            let options = ProcessOptions {
+                combined_dir: Some(root.join("pairs")),
+                ..options
+            };
+            process_images(&rgb_dir, &nir_dir, &options).unwrap()
+        };
+
+        run(
+            &planned.0,
+            ProcessOptions {
+                dry_run: true,
+                moves_manifest: Some(plan.clone()),
+                ..Default::default()
+            },
+        );
+        let moves = apply_manifest(&plan, None, &ProcessOptions::default()).unwrap();
+        std::fs::remove_file(&plan).unwrap();
+        let report = run(&direct.0, ProcessOptions::default());
+
+        assert_eq!(moves.len(), report.moves.len());
+        assert_eq!(layout(&planned.0), layout(&direct.0));
+    }
+    #[test]
+    fn apply_manifest_uses_planned_operation() {
+        let tmp = TempDir::new();
+        let (rgb_dir, nir_dir) = survey(&tmp.0);
+        let plan = tmp.0.join("plan.csv");
+        let options = ProcessOptions {
+            dry_run: true,
+            file_op: FileOp::Copy,
+            moves_manifest: Some(plan.clone()),
+            ..Default::default()
+        };
+        process_images(&rgb_dir, &nir_dir, &options).unwrap();
+        let before = layout(&tmp.0);
+
+        let e = apply_manifest(&plan, Some(FileOp::Symlink), &ProcessOptions::default());
+        assert!(e.is_err());
+        assert_eq!(layout(&tmp.0), before);
+
+        let moves_manifest = tmp.0.join("moves.csv");
+        let options = ProcessOptions {
+            moves_manifest: Some(moves_manifest.clone()),
+            ..Default::default()
+        };
+        let moves = apply_manifest(&plan, None, &options).unwrap();
+        assert_eq!(moves.len(), 2);
+        for m in &moves {
+            assert!(m.source.exists());
+            assert!(m.destination.exists());
+        }
+        let operations = read_csv(&moves_manifest).unwrap();
+        let operations = operations
+            .column(columns::OPERATION)
+            .unwrap()
+            .str()
+            .unwrap();
+        assert!(operations.into_iter().all(|op| op == Some("copy")));
+    }
+    #[test]
+    fn failed_apply_manifest_rolls_back() {
+        let tmp = TempDir::new();
+        let (rgb_dir, nir_dir) = survey(&tmp.0);
+        let plan = tmp.0.join("plan.csv");
+        let options = ProcessOptions {
+            dry_run: true,
+            combined_dir: Some(tmp.0.join("pairs")),
+            moves_manifest: Some(plan.clone()),
+            ..Default::default()
+        };
+        process_images(&rgb_dir, &nir_dir, &options).unwrap();
+        // A source removed after planning fails the apply partway through
+        std::fs::remove_file(nir_dir.join("2024-05-01003_NIR_2003.IIQ")).unwrap();
+        let before = layout(&tmp.0);
+
+        assert!(apply_manifest(&plan, None, &ProcessOptions::default()).is_err());
+        assert_eq!(layout(&tmp.0), before);
+    }
+
+    #[test]
+    fn invalid_dir_pattern_is_an_error() {
+        let tmp = TempDir::new();
+        survey(&tmp.0);
+
+        let e = process_session(&tmp.0, "YC[", "YD*", &ProcessOptions::default()).unwrap_err();
+        assert!(format!("{:#}", e).contains("'YC['"));
+        assert_eq!(find_dir_by_pattern(&tmp.0, "YC["), None);
+    }
+
+    #[test]
+    fn output_dirs_inside_band_are_not_rediscovered() {
+        let tmp = TempDir::new();
+        let (rgb_dir, nir_dir) = survey(&tmp.0);
+        let options = ProcessOptions {
+            max_depth: 1,
+            combined_dir: Some(rgb_dir.join("pairs")),
+            ..Default::default()
+        };
+        let report = process_images(&rgb_dir, &nir_dir, &options).unwrap();
+        assert_eq!(report.moves.len(), 8);
+
+        let (files, _) = find_option_files(&rgb_dir, &options).unwrap();
+        assert!(files.is_empty());
+    }
+
+    #[test]
+    fn parallel_search_finds_the_same_files() {
+        let tmp = TempDir::new();
+        for day in 1..=3 {
+            for flight in 1..=4 {
+                let dir = tmp
+                    .0
+                    .join(format!("day{}", day))
+                    .join(format!("flight{}", flight));
+                std::fs::create_dir_all(&dir).unwrap();
+                for event in 1..=5 {
+                    let name = format!("2024-05-0{}{:03}_RGB_{}.IIQ", day, event, flight);
+                    std::fs::write(dir.join(name), "").unwrap();
+                }
+                std::fs::write(dir.join("notes.txt"), "").unwrap();
+            }
+        }
+        std::fs::create_dir_all(tmp.0.join("day1").join("flight1").join("Unmatched")).unwrap();
+        std::fs::write(
+            tmp.0.join("day1/flight1/Unmatched/2024-05-01009_RGB_1.IIQ"),
+            "",
+        )
+        .unwrap();
+
+        for max_depth in [0, 1, 2, 3] {
+            let mut serial =
+                find_files_recursive(&tmp.0, &[".IIQ"], max_depth, &["Unmatched"]).unwrap();
+            let mut parallel =
+                find_files_recursive_parallel(&tmp.0, &[".IIQ"], max_depth, &["Unmatched"], 4)
+                    .unwrap();
+            serial.sort();
+            parallel.sort();
+            assert_eq!(parallel, serial);
+        }
+        assert_eq!(
+            find_files_recursive(&tmp.0, &[".IIQ"], 2, &[])
+                .unwrap()
+                .len(),
+            60
+        );
+    }
+
+    #[test]
+    fn watch_matches_files_as_they_arrive() {
+        let tmp = TempDir::new();
+        let rgb_dir = tmp.0.join("YC01");
+        let nir_dir = tmp.0.join("YD01");
+        std::fs::create_dir_all(&rgb_dir).unwrap();
+        std::fs::create_dir_all(&nir_dir).unwrap();
+        let write = |dir: &Path, name: &str| std::fs::write(dir.join(name), name).unwrap();
+        write(&rgb_dir, "2024-05-01001_RGB_1001.IIQ");
+        write(&rgb_dir, "2024-05-01002_RGB_1002.IIQ");
+        write(&nir_dir, "2024-05-01001_NIR_2001.IIQ");
+        write(&nir_dir, "2024-05-01003_NIR_2003.IIQ");
+
+        let pairs = tmp.0.join("pairs");
+        let watcher = {
+            let (rgb_dir, nir_dir) = (rgb_dir.clone(), nir_dir.clone());
+            let options = ProcessOptions {
+                combined_dir: Some(pairs.clone()),
+                ..Default::default()
+            };
+            std::thread::spawn(move || {
+                let poll = std::time::Duration::from_millis(20);
+                watch(&rgb_dir, &nir_dir, &options, poll, poll * 50)
+            })
+        };
+
+        std::thread::sleep(std::time::Duration::from_millis(300));
+        assert!(pairs.join("2024-05-01001_RGB_1001.IIQ").exists());
+        assert!(rgb_dir.join("2024-05-01002_RGB_1002.IIQ").exists());
+        assert!(nir_dir.join("2024-05-01003_NIR_2003.IIQ").exists());
+        write(&nir_dir, "2024-05-01002_NIR_2002.IIQ");
+
+        let report = watcher.join().unwrap().unwrap();
+        assert_eq!(report.moves.len(), 5);
+        assert!(pairs.join("2024-05-01002_NIR_2002.IIQ").exists());
+        assert!(nir_dir
+            .join("Unmatched")
+            .join("2024-05-01003_NIR_2003.IIQ")
+            .exists());
+    }
+
+    #[test]
+    fn failed_move_rolls_back_batch() {
+        let tmp = TempDir::new();
+        let (rgb_dir, _) = survey(&tmp.0);
+        let before = layout(&tmp.0);
+
+        let paths = [
+            rgb_dir.join("2024-05-01001_RGB_1001.IIQ"),
+            rgb_dir.join("2024-05-01002_RGB_1002.IIQ"),
+            rgb_dir.join("2024-05-01009_RGB_1009.IIQ"),
+            rgb_dir.join("2024-05-01003_RGB_1003.IIQ"),
+        ];
+        let dest_dir = tmp.0.join("out");
+        let e = move_files(
+            &paths,
+            &dest_dir,
+            false,
+            FileOp::Move,
+            CollisionPolicy::Error,
+            None,
+        )
+        .unwrap_err();
+
+        assert!(matches!(
+            e.downcast_ref::<IxMatchError>(),
+            Some(IxMatchError::Move { .. })
+        ));
+        assert_eq!(layout(&tmp.0), before);
+    }
+
+    #[test]
+    fn failed_parallel_move_rolls_back_run() {
+        let tmp = TempDir::new();
+        let (rgb_dir, nir_dir) = survey(&tmp.0);
+        let before = layout(&tmp.0);
+
+        let rgb_files = find_files(&rgb_dir, &[".IIQ"]).unwrap();
+        let mut nir_files = find_files(&nir_dir, &[".IIQ"]).unwrap();
+        nir_files.push(nir_dir.join("2024-05-01009_NIR_2009.IIQ"));
+        let options = ProcessOptions {
+            jobs: 4,
+            combined_dir: Some(tmp.0.join("pairs")),
+            ..Default::default()
+        };
+        let e =
+            process_file_lists(&rgb_dir, &nir_dir, &rgb_files, &nir_files, &options).unwrap_err();
+
+        assert!(matches!(
+            e.downcast_ref::<IxMatchError>(),
+            Some(IxMatchError::Move { .. })
+        ));
+        assert_eq!(layout(&tmp.0), before);
+    }
+
+    #[test]
+    fn collision_policies() {
+        let tmp = TempDir::new();
+        let (rgb_dir, _) = survey(&tmp.0);
+        let dest_dir = tmp.0.join("out");
+        std::fs::create_dir_all(&dest_dir).unwrap();
+        let name = "2024-05-01001_RGB_1001.IIQ";
+        std::fs::write(dest_dir.join(name), "existing").unwrap();
+        let paths = [
+            rgb_dir.join("2024-05-01002_RGB_1002.IIQ"),
+            rgb_dir.join(name),
+        ];
+        let move_with =
+            |collision| move_files(&paths, &dest_dir, false, FileOp::Copy, collision, None);
+
+        let before = layout(&tmp.0);
+        let e = move_with(CollisionPolicy::Error).unwrap_err();
+        assert!(matches!(
+            e.downcast_ref::<IxMatchError>(),
+            Some(IxMatchError::Collision(_))
+        ));
+        assert_eq!(layout(&tmp.0), before);
+
+        let moves = move_with(CollisionPolicy::Skip).unwrap();
+        assert_eq!(moves.len(), 1);
+        assert_eq!(
+            std::fs::read_to_string(dest_dir.join(name)).unwrap(),
+            "existing"
+        );
+
+        std::fs::remove_file(dest_dir.join("2024-05-01002_RGB_1002.IIQ")).unwrap();
+        let moves = move_with(CollisionPolicy::Rename).unwrap();
+        assert_eq!(
+            moves[1].destination,
+            dest_dir.join("2024-05-01001_RGB_1001_1.IIQ")
+        );
+        assert_eq!(
+            std::fs::read_to_string(&moves[1].destination).unwrap(),
+            name
+        );
+        assert_eq!(
+            std::fs::read_to_string(dest_dir.join(name)).unwrap(),
+            "existing"
+        );
+    }
+
+    #[test]
+    fn move_across_filesystems() {
+        // /dev/shm is usually a separate tmpfs, which exercises the copy fallback
+        let shm = Path::new("/dev/shm");
+        if !shm.is_dir() {
+            return;
+        }
+        let tmp = TempDir::new();
+        let other = TempDir::new_in(shm, 0);
+        let src = tmp.0.join("2024-05-01001_RGB_1001.IIQ");
+        std::fs::write(&src, "rgb").unwrap();
+        let modified = std::fs::metadata(&src).unwrap().modified().unwrap();
+
+        let dest = other.0.join("2024-05-01001_RGB_1001.IIQ");
+        move_file(&src, &dest, true).unwrap();
+
+        assert!(!src.exists());
+        assert_eq!(std::fs::read_to_string(&dest).unwrap(), "rgb");
+        assert_eq!(
+            std::fs::metadata(&dest).unwrap().modified().unwrap(),
+            modified
+        );
+    }
+
+    #[test]
+    fn undo_restores_each_file_op() {
+        for file_op in [FileOp::Move, FileOp::Copy, FileOp::Symlink] {
+            let tmp = TempDir::new();
+            let (rgb_dir, nir_dir) = survey(&tmp.0);
+            let before = layout(&tmp.0);
+            let manifest = tmp.0.join("moves.csv");
+            let options = ProcessOptions {
+                file_op,
+                combined_dir: Some(tmp.0.join("pairs")),
+                moves_manifest: Some(manifest.clone()),
+                ..Default::default()
+            };
+            let report = process_images(&rgb_dir, &nir_dir, &options).unwrap();
+            assert_eq!(report.moves.len(), 8);
+
+            undo_run(&manifest).unwrap();
+            std::fs::remove_file(&manifest).unwrap();
+            assert_eq!(layout(&tmp.0), before, "{:?}", file_op);
+        }
+    }
+    #[test]
+    fn undo_never_overwrites_a_source() {
+        let tmp = TempDir::new();
+        let (rgb_dir, nir_dir) = survey(&tmp.0);
+        let manifest = tmp.0.join("moves.csv");
+        let options = ProcessOptions {
+            moves_manifest: Some(manifest.clone()),
+            ..Default::default()
+        };
+        process_images(&rgb_dir, &nir_dir, &options).unwrap();
+        let source = rgb_dir.join("2024-05-01001_RGB_1001.IIQ");
+        std::fs::write(&source, "new").unwrap();
+
+        assert!(undo_run(&manifest).is_err());
+        assert_eq!(std::fs::read_to_string(&source).unwrap(), "new");
+        let moved = rgb_dir.join("Unmatched").join("2024-05-01001_RGB_1001.IIQ");
+        assert_eq!(
+            std::fs::read_to_string(moved).unwrap(),
+            "2024-05-01001_RGB_1001.IIQ"
+        );
+    }
+
+    #[test]
+    fn errors_are_typed() {
+        let tmp = TempDir::new();
+        let (rgb_dir, _) = survey(&tmp.0);
+        std::fs::create_dir_all(tmp.0.join("YC02")).unwrap();
+        let options = ProcessOptions::default();
+        let error = |e: anyhow::Error| e.downcast::<IxMatchError>().unwrap();
+
+        let e = process_session(&tmp.0, "YC*", "YD*", &options).unwrap_err();
+        assert!(matches!(
+            error(e),
+            IxMatchError::AmbiguousDirectory { matches, .. } if matches.len() == 2
+        ));
+        let e = process_session(&tmp.0, "YE*", "YD*", &options).unwrap_err();
+        assert!(matches!(
+            error(e),
+            IxMatchError::DirectoryNotFound { pattern, .. } if pattern == "YE*"
+        ));
+        let e = process_images(&rgb_dir, &tmp.0.join("YC01"), &options).unwrap_err();
+        assert!(matches!(error(e), IxMatchError::SameDirectory(_)));
+        let e = parse_iiq_filename("DSC_0001.IIQ").unwrap_err();
+        assert!(
+            matches!(error(e), IxMatchError::Parse { filename, .. } if filename == "DSC_0001.IIQ")
+        );
+    }
+
+    #[test]
+    fn one_to_one_returns_orphans_to_unmatched() {
+        let rgb = [
+            PathBuf::from("2024-05-01001_RGB_1001.IIQ"),
+            PathBuf::from("2024-05-01001_RGB_1002.IIQ"),
+            PathBuf::from("2024-05-01002_RGB_1003.IIQ"),
+        ];
+        let nir = [
+            PathBuf::from("2024-05-01001_NIR_2001.IIQ"),
+            PathBuf::from("2024-05-01002_NIR_2002.IIQ"),
+        ];
+        let frames =
+            match_frames(&make_iiq_df(&rgb).unwrap(), &make_iiq_df(&nir).unwrap()).unwrap();
+        assert_eq!(frames.matched.height(), 3);
+
+        let (frames, dropped) = enforce_one_to_one(frames).unwrap();
+        let pairs = matched_pairs(&frames.matched).unwrap();
+        let pairs: Vec<_> = pairs.iter().map(|p| (&p.rgb, &p.nir)).collect();
+        assert_eq!(pairs, [(&rgb[0], &nir[0]), (&rgb[2], &nir[1])]);
+        assert_eq!(
+            get_df_column_as_paths(&dropped, columns::PATH).unwrap(),
+            [rgb[1].clone()]
+        );
+        assert_eq!(
+            get_df_column_as_paths(&frames.unmatched_rgb, columns::PATH).unwrap(),
+            [rgb[1].clone()]
+        );
+        assert_eq!(frames.unmatched_nir.height(), 0);
+    }
+
+    #[test]
+    fn dir_patterns_expand_braces() {
+        let mut expanded = expand_braces("Y{C,D}0{1,2}");
+        expanded.sort();
+        assert_eq!(expanded, ["YC01", "YC02", "YD01", "YD02"]);
+        assert_eq!(expand_braces("YC{01"), ["YC{01"]);
+
+        // Glob characters in the base directory are matched literally
+        let tmp = TempDir::new();
+        let base = tmp.0.join("survey [2024]");
+        let (rgb_dir, nir_dir) = survey(&base);
+        assert_eq!(
+            find_dirs_by_pattern(&base, "Y{C,D}0?").unwrap(),
+            [rgb_dir.clone(), nir_dir]
+        );
+        assert_eq!(find_dir_by_pattern(&base, "{YC,RGB}*"), Some(rgb_dir));
+    }
+
+    #[test]
+    fn manifest_diff_reports_added_changed_and_removed_pairs() {
+        let tmp = TempDir::new();
+        let paths = |names: &[&str]| -> Vec<PathBuf> { names.iter().map(PathBuf::from).collect() };
+        let matched = |rgb: &[PathBuf], nir: &[PathBuf]| {
+            let rgb_df = make_iiq_df(rgb).unwrap();
+            let nir_df = make_iiq_df(nir).unwrap();
+            match_frames(&rgb_df, &nir_df).unwrap().matched
+        };
+        let rgb = paths(&[
+            "2024-05-01001_RGB_1001.IIQ",
+            "2024-05-01002_RGB_1002.IIQ",
+            "2024-05-01003_RGB_1003.IIQ",
+            "2024-05-01004_RGB_1004.IIQ",
+        ]);
+        let nir = paths(&[
+            "2024-05-01001_NIR_2001.IIQ",
+            "2024-05-01002_NIR_2002.IIQ",
+            "2024-05-01003_NIR_2003.IIQ",
+            "2024-05-01002_NIR_2012.IIQ",
+            "2024-05-01004_NIR_2004.IIQ",
+        ]);
+        let manifest = tmp.0.join("matches.csv");
+        write_match_manifest(&matched(&rgb[..3], &nir[..3]), &manifest).unwrap();
+
+        let unchanged = diff_match_manifest(&matched(&rgb[..3], &nir[..3]), &manifest).unwrap();
+        assert!(unchanged.is_empty());
+
+        let current = matched(
+            &[rgb[0].clone(), rgb[1].clone(), rgb[3].clone()],
+            &[nir[0].clone(), nir[3].clone(), nir[4].clone()],
+        );
+        let diff = diff_match_manifest(&current, &manifest).unwrap();
+        assert_eq!(diff.added, [(rgb[3].clone(), nir[4].clone())]);
+        assert_eq!(
+            diff.changed,
+            [(
+                (rgb[1].clone(), nir[1].clone()),
+                (rgb[1].clone(), nir[3].clone())
+            )]
+        );
+        assert_eq!(diff.removed, [(rgb[2].clone(), nir[2].clone())]);
+    }
+
+    #[test]
+    fn id_modulo_splits_a_shared_directory() {
+        assert!(id_modulo(0, 0, 0).is_err());
+        assert!(id_modulo(2, 2, 0).is_err());
+        assert!(id_modulo(2, -1, 0).is_err());
+
+        let tmp = TempDir::new();
+        // Odd IDs are RGB and even IDs NIR; event 004 only has an RGB capture
+        for (event, id) in [(1, 1), (1, 2), (2, 3), (2, 4), (3, 5), (3, 6), (4, 7)] {
+            let name = format!("2024-05-01{:03}_IIQ_{}.IIQ", event, id);
+            std::fs::write(tmp.0.join(&name), &name).unwrap();
+        }
+        std::fs::write(tmp.0.join("notes_IIQ.IIQ"), "").unwrap();
+        let options = ProcessOptions {
+            combined_dir: Some(tmp.0.join("pairs")),
+            ..Default::default()
+        };
+        let report = process_single_dir(
+            &tmp.0,
+            id_modulo(2, 1, 0).unwrap(),
+            id_modulo(2, 0, 0).unwrap(),
+            &options,
+        )
+        .unwrap();
+
+        assert_eq!((report.rgb_total, report.nir_total), (4, 3));
+        assert_eq!(report.matched, 3);
+        assert_eq!(report.unmatched_rgb, 1);
+        assert!(tmp.0.join("pairs").join("2024-05-01002_IIQ_4.IIQ").exists());
+        assert!(tmp
+            .0
+            .join("Unmatched")
+            .join("2024-05-01004_IIQ_7.IIQ")
+            .exists());
+        assert!(tmp.0.join("notes_IIQ.IIQ").exists());
+    }
+
+    #[test]
+    fn name_case_applies_before_collisions_are_renamed() {
+        let tmp = TempDir::new();
+        let (rgb_dir, nir_dir) = survey(&tmp.0);
+        let unmatched = rgb_dir.join("Unmatched");
+        std::fs::create_dir_all(&unmatched).unwrap();
+        std::fs::write(unmatched.join("2024-05-01001_rgb_1001.iiq"), "existing").unwrap();
+        let options = ProcessOptions {
+            name_case: Some(NameCase::Lower),
+            collision: CollisionPolicy::Rename,
+            ..Default::default()
+        };
+        let report = process_images(&rgb_dir, &nir_dir, &options).unwrap();
+
+        let destinations: Vec<_> = report.moves.iter().map(|m| &m.destination).collect();
+        assert_eq!(
+            destinations,
+            [
+                &nir_dir.join("Unmatched").join("2024-05-01005_nir_2005.iiq"),
+                &unmatched.join("2024-05-01001_rgb_1001_1.iiq"),
+            ]
+        );
+        assert_eq!(
+            std::fs::read_to_string(unmatched.join("2024-05-01001_rgb_1001.iiq")).unwrap(),
+            "existing"
+        );
+    }
+
+    #[test]
+    fn match_manifest_round_trip() {
+        let tmp = TempDir::new();
+        let (rgb_dir, nir_dir) = survey(&tmp.0);
+        let frames = plan_images(&rgb_dir, &nir_dir).unwrap();
+        let manifest = tmp.0.join("matches.csv");
+        write_match_manifest(&frames.matched, &manifest).unwrap();
+
+        let pairs = read_match_manifest(&manifest).unwrap();
+        assert_eq!(pairs, matched_pairs(&frames.matched).unwrap());
+        let events: Vec<&str> = pairs.iter().map(|p| p.event.as_str()).collect();
+        assert_eq!(events, ["002", "003", "004"]);
+        assert_eq!(pairs[0].date, "2024-05-01");
+        assert_eq!(pairs[0].nir, nir_dir.join("2024-05-01002_NIR_2002.IIQ"));
+    }
+
+    #[test]
+    fn rerun_with_depth_moves_nothing() {
+        let tmp = TempDir::new();
+        let (rgb_dir, nir_dir) = survey(&tmp.0);
+        let flight = rgb_dir.join("flight2");
+        std::fs::create_dir_all(&flight).unwrap();
+        std::fs::write(flight.join("2024-05-01006_RGB_1006.IIQ"), "").unwrap();
+        let options = ProcessOptions {
+            max_depth: 2,
+            ..Default::default()
+        };
+
+        let report = process_images(&rgb_dir, &nir_dir, &options).unwrap();
+        assert_eq!(report.rgb_total, 5);
+        assert_eq!(report.moves.len(), 3);
+        let after_first = layout(&tmp.0);
+
+        let report = process_images(&rgb_dir, &nir_dir, &options).unwrap();
+        assert!(report.moves.is_empty());
+        assert_eq!(layout(&tmp.0), after_first);
+    }
+
+    #[test]
+    fn malformed_names_are_set_aside() {
+        let tmp = TempDir::new();
+        let (rgb_dir, nir_dir) = survey(&tmp.0);
+        std::fs::write(rgb_dir.join("DSC_0001.IIQ"), "").unwrap();
+        std::fs::write(nir_dir.join("2024-13-01006_NIR_2006.IIQ"), "").unwrap();
+
+        let e = process_images(&rgb_dir, &nir_dir, &ProcessOptions::default()).unwrap_err();
+        assert!(matches!(
+            e.downcast_ref::<IxMatchError>(),
+            Some(IxMatchError::Parse { .. })
+        ));
+
+        let options = ProcessOptions {
+            skip_malformed: true,
+            ..Default::default()
+        };
+        let report = process_images(&rgb_dir, &nir_dir, &options).unwrap();
+        assert_eq!(report.malformed, 2);
+        assert_eq!(report.matched, 3);
+        assert!(rgb_dir.join("Malformed").join("DSC_0001.IIQ").exists());
+        assert!(nir_dir
+            .join("Malformed")
+            .join("2024-13-01006_NIR_2006.IIQ")
+            .exists());
+    }
+}
@@ -1,10 +1,13 @@
 use std::path::PathBuf;
+use std::time::Duration;
 
-use anyhow::Result;
+use anyhow::{bail, Result};
+use chrono::NaiveDate;
 use clap::Parser;
-use polars::prelude::*;
-
-use ix_match::{find_dir_by_pattern, find_files, make_iiq_df, move_unmatched_files};
+use ix_match::{
+    apply_manifest, find_dir_by_pattern, process_images, undo_run, validate, watch,
+    CollisionPolicy, FileOp, NameCase, ProcessOptions,
+};
 
 /// Match RGB and NIR IIQ files and move unmatched images to a new subdirectory.
 /// Helps to sort images from an aerial survey using PhaseOne cameras as a preprocessing step for
@@ -20,10 +23,203 @@ struct Args {
     #[arg(short, long, action = clap::ArgAction::SetTrue, default_value = "false")]
     dry_run: bool,
 
+    /// Only print warnings
+    #[arg(short, long, action = clap::ArgAction::SetTrue, default_value = "false")]
+    quiet: bool,
+
+    /// Copy unmatched files instead of moving them
+    #[arg(short, long, action = clap::ArgAction::SetTrue, default_value = "false")]
+    copy: bool,
+
+    /// Symlink unmatched files instead of moving them
+    #[arg(short, long, action = clap::ArgAction::SetTrue, default_value = "false", conflicts_with = "copy")]
+    symlink: bool,
+
+    /// What to do when a file already exists at its destination
+    #[arg(long, value_enum, default_value_t = CollisionPolicy::Error)]
+    on_collision: CollisionPolicy,
+
+    /// Change the case of file names as they are moved
+    #[arg(long, value_enum)]
+    name_case: Option<NameCase>,
+
     /// The new subdirectory name where unmatched files will be moved
     #[arg(short, default_value = "Unmatched")]
     output_dir: String,
 
+    /// Write a CSV manifest of the matched RGB/NIR pairs to this path
+    #[arg(short, long)]
+    manifest: Option<PathBuf>,
+
+    /// Use each file in at most one pair when several share an event number
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value = "false")]
+    one_to_one: bool,
+
+    /// Only process files dated on or after this day (YYYY-MM-DD)
+    #[arg(long)]
+    start: Option<NaiveDate>,
+
+    /// Only process files dated on or before this day (YYYY-MM-DD)
+    #[arg(long)]
+    end: Option<NaiveDate>,
+
+    /// Treat files dated before this day as malformed (YYYY-MM-DD)
+    #[arg(long)]
+    earliest_plausible: Option<NaiveDate>,
+
+    /// Treat files dated after this day as malformed (YYYY-MM-DD)
+    #[arg(long)]
+    latest_plausible: Option<NaiveDate>,
+
+    /// Skip files whose contents don't start with an IIQ/TIFF header
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value = "false")]
+    check_signatures: bool,
+
+    /// Move files with unparseable names aside instead of stopping
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value = "false")]
+    skip_malformed: bool,
+
+    /// The subdirectory name where files with unparseable names will be moved
+    #[arg(long, default_value = "Malformed")]
+    malformed_dir: String,
+
+    /// Number of characters to skip at the start of each file name before the date
+    #[arg(long, default_value_t = 0)]
+    name_prefix_len: usize,
+
+    /// How many levels of subdirectories to search for IIQ files
+    #[arg(long, default_value_t = 0)]
+    max_depth: usize,
+
+    /// Move both files of every matched pair into this directory
+    #[arg(long)]
+    combined_dir: Option<PathBuf>,
+
+    /// Prefix files in the combined directory with their pair number
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value = "false", requires = "combined_dir")]
+    index_pairs: bool,
+
+    /// Move the RGB file of every matched pair into this directory
+    #[arg(long, conflicts_with = "combined_dir")]
+    matched_rgb_dir: Option<PathBuf>,
+
+    /// Move the NIR file of every matched pair into this directory
+    #[arg(long, conflicts_with = "combined_dir")]
+    matched_nir_dir: Option<PathBuf>,
+
+    /// Only consider the first N files of each band, ordered by date and event
+    #[arg(long)]
+    limit: Option<usize>,
+
+    /// Move files smaller than this many bytes aside instead of matching them
+    #[arg(long, default_value_t = 0)]
+    min_size: u64,
+
+    /// The subdirectory name where undersized files will be moved
+    #[arg(long, default_value = "Undersized")]
+    undersized_dir: String,
+
+    /// Move undersized files from both bands into this one directory
+    #[arg(long)]
+    shared_undersized_dir: Option<PathBuf>,
+
+    /// Put each pair in its own numbered subdirectory of the combined directory
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value = "false", requires = "combined_dir")]
+    pair_subdirs: bool,
+
+    /// Check that every copied file is byte-identical to its source
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value = "false")]
+    verify: bool,
+
+    /// Retry a file transfer this many times if it fails with a transient error
+    #[arg(long, default_value_t = 0)]
+    retries: u32,
+
+    /// Number of files to transfer, and directories to search, at once
+    #[arg(short, long, default_value_t = 1)]
+    jobs: usize,
+
+    /// Keep the subdirectory structure of the band directory when moving files aside
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value = "false")]
+    mirror_subdirs: bool,
+
+    /// Write a CSV record of every file moved to this path
+    #[arg(long)]
+    moves_manifest: Option<PathBuf>,
+
+    /// Write a match_manifest.csv into each band directory after moving files
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value = "false")]
+    manifest_in_outputs: bool,
+
+    /// Reverse the run recorded in this moves manifest instead of matching files, using the
+    /// file operation recorded in it
+    #[arg(long)]
+    undo: Option<PathBuf>,
+
+    /// Carry out the moves planned in this moves manifest instead of matching files, using the
+    /// file operation recorded for each
+    #[arg(long, conflicts_with = "undo")]
+    apply: Option<PathBuf>,
+
+    /// File extension of the images to match (may be given more than once)
+    #[arg(short, long = "extension", default_value = ".IIQ")]
+    extensions: Vec<String>,
+
+    /// Try writing to all directories involved before moving any files (a dry run only detects
+    /// directories that are read-only for everyone)
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value = "false")]
+    check_writable: bool,
+
+    /// Never search subdirectories whose names match this pattern (may be given more than once)
+    #[arg(long = "ignore-dir")]
+    ignore_dirs: Vec<String>,
+
+    /// Skip files that can't be opened instead of stopping
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value = "false")]
+    skip_inaccessible: bool,
+
+    /// Skip subdirectories that can't be read instead of stopping
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value = "false")]
+    skip_unreadable_dirs: bool,
+
+    /// Ignore files and subdirectories whose names start with a dot
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value = "false")]
+    skip_hidden: bool,
+
+    /// Ignore files that are exact copies of another file in the same band
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value = "false")]
+    dedupe: bool,
+
+    /// Stop without moving files if more than this fraction (0 to 1) of files are unmatched
+    #[arg(long)]
+    max_unmatched_fraction: Option<f64>,
+
+    /// Warn about pairs whose RGB file is less than this many times the size of the NIR file
+    #[arg(long)]
+    min_size_ratio: Option<f64>,
+
+    /// Warn about pairs whose RGB file is more than this many times the size of the NIR file
+    #[arg(long)]
+    max_size_ratio: Option<f64>,
+
+    /// Only check the files for problems and report them, without moving anything
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value = "false")]
+    validate: bool,
+
+    /// Write the planned moves to this path as a shell script (a batch file on Windows) instead
+    /// of moving files
+    #[arg(long)]
+    move_script: Option<PathBuf>,
+
+    /// Leave unmatched files where they are instead of moving them
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value = "false")]
+    keep_unmatched: bool,
+
+    /// Keep matching pairs as files arrive, until none have arrived for this many seconds, then
+    /// handle the files left over. Needs --combined-dir or both matched directories
+    #[arg(long, value_name = "SECONDS")]
+    watch: Option<u64>,
+
     /// Pattern for finding directory containing RGB files
     #[arg(short, long, default_value = "YC*")]
     rgb_pattern: String,
@@ -33,8 +229,97 @@ struct Args {
     nir_pattern: String,
 }
 
+/// Prints library log records to stdout as plain lines.
+struct StdoutLogger;
+
+impl log::Log for StdoutLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            println!("{}", record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StdoutLogger = StdoutLogger;
+
 fn main() -> Result<()> {
     let args = Args::parse();
+    log::set_logger(&LOGGER).expect("Logger already set");
+    log::set_max_level(if args.quiet {
+        log::LevelFilter::Warn
+    } else {
+        log::LevelFilter::Info
+    });
+    let file_op = if args.copy {
+        FileOp::Copy
+    } else if args.symlink {
+        FileOp::Symlink
+    } else {
+        FileOp::Move
+    };
+    if let Some(manifest) = &args.undo {
+        return undo_run(manifest);
+    }
+
+    let options = ProcessOptions {
+        output_dir: args.output_dir,
+        dry_run: args.dry_run,
+        file_op,
+        collision: args.on_collision,
+        manifest: args.manifest,
+        one_to_one: args.one_to_one,
+        start: args.start,
+        end: args.end,
+        earliest_plausible: args.earliest_plausible,
+        latest_plausible: args.latest_plausible,
+        check_signatures: args.check_signatures,
+        skip_malformed: args.skip_malformed,
+        malformed_dir: args.malformed_dir,
+        name_prefix_len: args.name_prefix_len,
+        max_depth: args.max_depth,
+        combined_dir: args.combined_dir,
+        index_pairs: args.index_pairs,
+        pair_subdirs: args.pair_subdirs,
+        matched_rgb_dir: args.matched_rgb_dir,
+        matched_nir_dir: args.matched_nir_dir,
+        limit: args.limit,
+        min_size: args.min_size,
+        undersized_dir: args.undersized_dir,
+        shared_undersized_dir: args.shared_undersized_dir,
+        verify: args.verify,
+        retries: args.retries,
+        jobs: args.jobs,
+        mirror_subdirs: args.mirror_subdirs,
+        moves_manifest: args.moves_manifest,
+        manifest_in_outputs: args.manifest_in_outputs,
+        extensions: args.extensions,
+        check_writable: args.check_writable,
+        ignore_dirs: args.ignore_dirs,
+        skip_inaccessible: args.skip_inaccessible,
+        skip_unreadable_dirs: args.skip_unreadable_dirs,
+        skip_hidden: args.skip_hidden,
+        dedupe: args.dedupe,
+        max_unmatched_fraction: args.max_unmatched_fraction,
+        min_size_ratio: args.min_size_ratio,
+        max_size_ratio: args.max_size_ratio,
+        move_script: args.move_script,
+        name_case: args.name_case,
+        keep_unmatched: args.keep_unmatched,
+        cancel: None,
+    };
+    if let Some(plan) = &args.apply {
+        // Only an explicit --copy or --symlink has to agree with the planned operations
+        let requested = (args.copy || args.symlink).then_some(file_op);
+        apply_manifest(plan, requested, &options)?;
+        return Ok(());
+    }
+
     let iiq_dir = args.iiq_dir;
 
     let yc_dir = find_dir_by_pattern(&iiq_dir, args.rgb_pattern.as_str());
@@ -46,56 +331,44 @@ fn main() -> Result<()> {
     let yc_dir = yc_dir.expect("RGB directory doesn't exist");
     let yd_dir = yd_dir.expect("NIR directory doesn't exist");
 
-    let yc_iiq_files = find_files(&yc_dir, ".IIQ")?;
-    let yd_iiq_files = find_files(&yd_dir, ".IIQ")?;
-
-    // Create dataframes
-    let rgb_df = make_iiq_df(&yc_iiq_files)?;
-    let nir_df = make_iiq_df(&yd_iiq_files)?;
-
-    let matched_df = rgb_df.inner_join(&nir_df, &["Event"], &["Event"])?;
-    println!("Found IIQs!");
-    println!(
-        "RGB: {}, NIR: {} ({} match)",
-        yc_iiq_files.len(),
-        yd_iiq_files.len(),
-        matched_df.height()
-    );
-
-    let joined_df = rgb_df.outer_join(&nir_df, &["Event"], &["Event"])?;
-
-    if matched_df.height() < joined_df.height() {
-        println!(
-            "Moving unmatched files to '{}/' sub-directories",
-            args.output_dir
-        );
-    } else {
-        println!("All files matched!");
+    if args.validate {
+        let report = validate(&yc_dir, &yd_dir)?;
+        for path in &report.malformed {
+            log::warn!("{} doesn't follow the IIQ naming scheme", path.display());
+        }
+        for path in &report.out_of_range {
+            log::warn!("{} is dated before 2000 or in the future", path.display());
+        }
+        for path in &report.empty {
+            log::warn!("{} is empty", path.display());
+        }
+        for (band, events) in [
+            ("RGB", &report.duplicate_rgb_events),
+            ("NIR", &report.duplicate_nir_events),
+        ] {
+            for event in events {
+                log::warn!("Event {} appears in more than one {} file", event, band);
+            }
+        }
+        if !report.is_clean() {
+            bail!("Validation found problems");
+        }
+        log::info!("No problems found");
+        return Ok(());
     }
 
-    let mask = joined_df.column("Type")?.is_null();
-    let unmatched_nir_df = joined_df.filter(&mask)?;
-    if unmatched_nir_df.height() > 0 {
-        move_unmatched_files(
-            &unmatched_nir_df,
+    if let Some(idle) = args.watch {
+        watch(
+            &yc_dir,
             &yd_dir,
-            "Path_right",
-            &args.output_dir,
-            args.dry_run,
+            &options,
+            Duration::from_secs(1),
+            Duration::from_secs(idle),
         )?;
+        return Ok(());
     }
 
-    let mask = joined_df.column("Type_right")?.is_null();
-    let unmatched_rgb_df = joined_df.filter(&mask)?;
-    if unmatched_rgb_df.height() > 0 {
-        move_unmatched_files(
-            &unmatched_rgb_df,
-            &yc_dir,
-            "Path",
-            &args.output_dir,
-            args.dry_run,
-        )?;
-    }
+    process_images(&yc_dir, &yd_dir, &options)?;
 
     Ok(())
 }